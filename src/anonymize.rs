@@ -0,0 +1,199 @@
+use std::path::Path;
+
+use crate::protobuf::{self, Field, WireValue};
+use crate::tray_files::TrayItem;
+use crate::tray_metadata::{self, FieldValue};
+use crate::constants;
+
+/// Creator values written in place of the real ones when packing items for public sharing.
+pub struct Anonymizer {
+    pub creator_name: String,
+    pub creator_id: u64,
+}
+
+impl Anonymizer {
+    /// Copy of the item carrying the anonymous creator, used for output names and bundle manifests.
+    pub fn anonymize_item(&self, tray_item: &TrayItem) -> TrayItem {
+        let mut anonymized_item = tray_item.clone();
+        anonymized_item.creator_name = self.creator_name.clone();
+        anonymized_item.creator_id = self.creator_id;
+        return anonymized_item;
+    }
+
+    /// Content of one file of the item with the creator replaced. A binary that can't be decoded gives None, leaving it
+    /// out is safer than leaking the creator. So does an item whose creator name or id couldn't be read from the
+    /// trayitem, there would be nothing to look for in its binaries.
+    pub fn anonymize_file(&self, tray_item: &TrayItem, path: &Path, content: &[u8]) -> Option<Vec<u8>> {
+        if tray_item.creator_name.is_empty() || tray_item.creator_id == 0 {
+            return None;
+        }
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+        if extension == constants::TRAYITEM_EXT {
+            return tray_metadata::set_fields(content, &[
+                (constants::TRAYITEM_CREATOR_NAME_FIELD, FieldValue::Text(self.creator_name.clone())),
+                (constants::TRAYITEM_CREATOR_ID_FIELD, FieldValue::Number(self.creator_id)),
+            ]);
+        }
+        if constants::CREATOR_REFERENCING_EXTENSIONS.contains(&extension) {
+            return self.anonymize_binary(tray_item, content);
+        }
+        return Some(content.to_vec());
+    }
+
+    /// The binaries have no known schema, so every string equal to the original creator name is replaced wherever it
+    /// is nested, and the original creator id only in the creator id fields. A length header in front of the message
+    /// is kept in sync.
+    fn anonymize_binary(&self, tray_item: &TrayItem, content: &[u8]) -> Option<Vec<u8>> {
+        let has_length_header = content.len() >= constants::TRAYITEM_HEADER_SIZE
+            && content[4..8] == ((content.len() - constants::TRAYITEM_HEADER_SIZE) as u32).to_le_bytes();
+        let header_sizes: &[usize] = if has_length_header { &[constants::TRAYITEM_HEADER_SIZE] } else { &[0, 4, constants::TRAYITEM_HEADER_SIZE] };
+        for header_size in header_sizes {
+            let message = &content[*header_size..];
+            if let Some(fields) = protobuf::read_fields(message) {
+                match self.rewrite_message(tray_item, &fields, 0).ok()? {
+                    Some(rewritten_message) => {
+                        if has_length_header {
+                            return Some(tray_metadata::with_header(&content[..constants::TRAYITEM_HEADER_SIZE], &rewritten_message));
+                        }
+                        let mut rewritten_content = content[..*header_size].to_vec();
+                        rewritten_content.extend_from_slice(&rewritten_message);
+                        return Some(rewritten_content);
+                    },
+                    None => {
+                        return Some(content.to_vec());
+                    }
+                }
+            }
+        }
+        return None;
+    }
+
+    /// Returns the re-encoded message when something was replaced in it or in one of its nested messages. A message
+    /// holding the creator name next to the creator id in an unknown field gives an error, the id would leak.
+    fn rewrite_message(&self, tray_item: &TrayItem, fields: &[Field], depth: usize) -> Result<Option<Vec<u8>>, ()> {
+        let original_name = tray_item.creator_name.as_bytes();
+        let original_id = tray_item.creator_id;
+        let mut holds_creator_name = false;
+        let mut replacements: Vec<Option<Vec<u8>>> = Vec::with_capacity(fields.len());
+        for field in fields {
+            let replacement = match field.value {
+                WireValue::LengthDelimited(bytes) if bytes == original_name => {
+                    holds_creator_name = true;
+                    Some(self.creator_name.as_bytes().to_vec())
+                },
+                WireValue::LengthDelimited(bytes) if depth < constants::PROTOBUF_MAX_DEPTH => {
+                    match protobuf::read_fields(bytes) {
+                        Some(nested_fields) => self.rewrite_message(tray_item, &nested_fields, depth + 1)?,
+                        None => None
+                    }
+                },
+                _ => None
+            };
+            replacements.push(replacement);
+        }
+        let mut changed = replacements.iter().any(|replacement| replacement.is_some());
+        let mut rewritten_fields: Vec<Field> = Vec::with_capacity(fields.len());
+        for (field, replacement) in fields.iter().zip(&replacements) {
+            let is_creator_id_field = constants::CREATOR_ID_FIELDS.contains(&field.number);
+            let value = match (replacement, field.value) {
+                (Some(bytes), _) => WireValue::LengthDelimited(bytes),
+                (None, WireValue::Varint(value)) | (None, WireValue::Fixed64(value)) if value == original_id && !is_creator_id_field && holds_creator_name => {
+                    return Err(());
+                },
+                (None, WireValue::Varint(value)) if value == original_id && is_creator_id_field => {
+                    changed = true;
+                    WireValue::Varint(self.creator_id)
+                },
+                (None, WireValue::Fixed64(value)) if value == original_id && is_creator_id_field => {
+                    changed = true;
+                    WireValue::Fixed64(self.creator_id)
+                },
+                (None, value) => value
+            };
+            rewritten_fields.push(Field { number: field.number, value });
+        }
+        if !changed {
+            return Ok(None);
+        }
+        return Ok(Some(protobuf::write_fields(&rewritten_fields)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::tray_metadata::tests::build_trayitem;
+    use crate::tray_metadata::TrayMetadata;
+
+    fn get_tray_item(creator_name: &str, creator_id: u64) -> TrayItem {
+        return TrayItem {
+            path: PathBuf::from("0x00000001!0x00aa12f12d6202c5.trayitem"),
+            name: String::from("Villa Rosa"),
+            creator_name: String::from(creator_name),
+            creator_id,
+            id: 0xaa12f12d6202c5,
+            file_type: constants::HOUSEHOLD_TRAYITEM_TYPE,
+            filename: String::from("0x00000001!0x00aa12f12d6202c5.trayitem"),
+            modified: SystemTime::UNIX_EPOCH,
+        };
+    }
+
+    fn get_anonymizer() -> Anonymizer {
+        return Anonymizer { creator_name: String::from(constants::ANONYMOUS_CREATOR_NAME), creator_id: constants::ANONYMOUS_CREATOR_ID };
+    }
+
+    #[test]
+    fn anonymize_file_replaces_the_creator_of_a_trayitem() {
+        let tray_item = get_tray_item("simmer42", 0x1234_5678_9abc);
+        let content = build_trayitem("Villa Rosa", 0x1234_5678_9abc, "simmer42");
+        let anonymized_content = get_anonymizer().anonymize_file(&tray_item, &tray_item.path, &content).unwrap();
+        let metadata = TrayMetadata::parse(&anonymized_content).unwrap();
+        assert_eq!(metadata.name, "Villa Rosa");
+        assert_eq!(metadata.creator_name, constants::ANONYMOUS_CREATOR_NAME);
+        assert_eq!(metadata.creator_id, constants::ANONYMOUS_CREATOR_ID);
+    }
+
+    #[test]
+    fn anonymize_file_fails_without_the_original_creator() {
+        let content = build_trayitem("Villa Rosa", 0x1234_5678_9abc, "simmer42");
+        for tray_item in [get_tray_item("", 0x1234_5678_9abc), get_tray_item("simmer42", 0)] {
+            assert!(get_anonymizer().anonymize_file(&tray_item, Path::new("0x00000001!0x00aa12f12d6202c5.householdbinary"), &content).is_none());
+        }
+    }
+
+    /// A household binary holding the creator record (name and id) inside the household message, next to the household
+    /// account field.
+    fn build_binary(creator_name: &str, creator_id: u64, creator_id_field: u32, account_id: u64) -> Vec<u8> {
+        let creator = protobuf::write_fields(&[
+            Field { number: constants::TRAYITEM_CREATOR_NAME_FIELD, value: WireValue::LengthDelimited(creator_name.as_bytes()) },
+            Field { number: creator_id_field, value: WireValue::Varint(creator_id) },
+        ]);
+        let household = protobuf::write_fields(&[
+            Field { number: 1, value: WireValue::LengthDelimited(b"Villa Rosa") },
+            Field { number: 2, value: WireValue::Varint(account_id) },
+            Field { number: 3, value: WireValue::LengthDelimited(&creator) },
+        ]);
+        return tray_metadata::with_header(&[0u8; 4], &protobuf::write_fields(&[Field { number: 1, value: WireValue::LengthDelimited(&household) }]));
+    }
+
+    #[test]
+    fn anonymize_file_keeps_the_creator_id_value_in_other_fields() {
+        let tray_item = get_tray_item("simmer42", 0x1234_5678_9abc);
+        let path = Path::new("0x00000000!0x00aa12f12d6202c5.householdbinary");
+        // The account field holds the same value as the creator id, it isn't a creator id field and is kept.
+        let content = build_binary("simmer42", 0x1234_5678_9abc, constants::TRAYITEM_CREATOR_ID_FIELD, 0x1234_5678_9abc);
+        let anonymized_content = get_anonymizer().anonymize_file(&tray_item, path, &content).unwrap();
+        assert_eq!(anonymized_content, build_binary(constants::ANONYMOUS_CREATOR_NAME, constants::ANONYMOUS_CREATOR_ID, constants::TRAYITEM_CREATOR_ID_FIELD, 0x1234_5678_9abc));
+    }
+
+    #[test]
+    fn anonymize_file_refuses_a_creator_id_in_an_unknown_field() {
+        let tray_item = get_tray_item("simmer42", 0x1234_5678_9abc);
+        let path = Path::new("0x00000000!0x00aa12f12d6202c5.householdbinary");
+        let content = build_binary("simmer42", 0x1234_5678_9abc, 8, 1);
+        assert!(get_anonymizer().anonymize_file(&tray_item, path, &content).is_none());
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use chrono::Local;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::utils::Outcome;
+use crate::{constants, journal, prompts, utils};
+
+/// A snapshot only lists the Tray files, their content lives once per hash in the objects folder of the store.
+#[derive(Deserialize, Serialize)]
+pub struct Snapshot {
+    pub created: String,
+    pub tray_folder: PathBuf,
+    pub files: Vec<SnapshotFile>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SnapshotFile {
+    pub filename: String,
+    pub hash: String,
+    pub size: u64,
+    pub modified: u64,
+}
+
+pub fn get_backup_store(store_override: Option<&Path>) -> Option<PathBuf> {
+    let store = find_backup_store(store_override);
+    if let Some(store) = &store {
+        utils::print_debug(format!("Using backup store {}", store.display()));
+    }
+    return store;
+}
+
+fn find_backup_store(store_override: Option<&Path>) -> Option<PathBuf> {
+    match store_override {
+        Some(store) => {
+            return Some(store.to_path_buf());
+        },
+        None => {
+            match ProjectDirs::from("", "", constants::APPLICATION_NAME) {
+                Some(project_dirs) => {
+                    return Some(project_dirs.data_dir().join(constants::BACKUP_STORE_FOLDERNAME));
+                },
+                None => {
+                    utils::print_error(String::from("Couldn't find the data directory, use --store to set the backup store location!"));
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Objects are named after the SHA-256 of their content, any other hash comes from a damaged snapshot.
+pub fn get_object_path(store: &Path, hash: &str) -> Option<PathBuf> {
+    if hash.len() != 64 || !hash.chars().all(|character| character.is_ascii_hexdigit()) {
+        return None;
+    }
+    return Some(store.join(constants::BACKUP_OBJECTS_FOLDERNAME).join(&hash[..2]).join(hash));
+}
+
+pub fn backup(tray_folder: &Path, store: &Path, keep: Option<usize>) -> Outcome {
+    let files = match utils::list_tray_folder(tray_folder) {
+        Some(files) => files,
+        None => {
+            return Outcome::Failure;
+        }
+    };
+    let snapshots_folder = store.join(constants::BACKUP_SNAPSHOTS_FOLDERNAME);
+    if fs::create_dir_all(&snapshots_folder).is_err() {
+        utils::print_error(format!("Unable to create the backup store {}!", store.display()));
+        return Outcome::Failure;
+    }
+    let mut snapshot = Snapshot {
+        created: Local::now().to_rfc3339(),
+        tray_folder: tray_folder.to_path_buf(),
+        files: Vec::new(),
+    };
+    let mut stored_files = 0;
+    for path in files {
+        let filename = match path.file_name().and_then(|filename| filename.to_str()) {
+            Some(filename) => String::from(filename),
+            None => {
+                utils::print_warning(format!("Couldn't parse file name, skipping! ({})", path.display()));
+                continue;
+            }
+        };
+        match store_object(store, &path) {
+            Some((hash, is_new)) => {
+                if is_new {
+                    stored_files += 1;
+                }
+                let metadata = fs::metadata(&path).ok();
+                let modified = metadata.as_ref().and_then(|metadata| metadata.modified().ok()).unwrap_or(UNIX_EPOCH);
+                snapshot.files.push(SnapshotFile {
+                    filename,
+                    hash,
+                    size: metadata.map(|metadata| metadata.len()).unwrap_or(0),
+                    modified: modified.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0),
+                });
+            },
+            None => {
+                utils::print_error(format!("Couldn't back up {}, the snapshot is aborted!", filename));
+                return Outcome::Failure;
+            }
+        }
+    }
+    let name = utils::get_new_timestamp_name(&snapshots_folder, ".toml");
+    match toml::to_string(&snapshot) {
+        Ok(content) => {
+            if fs::write(snapshots_folder.join(format!("{}.toml", name)), content).is_err() {
+                utils::print_error(format!("Couldn't write snapshot {}!", name));
+                return Outcome::Failure;
+            }
+        },
+        Err(_) => {
+            utils::print_error(format!("Couldn't serialize snapshot {}!", name));
+            return Outcome::Failure;
+        }
+    }
+    utils::print_success(format!("Snapshot {} created with {} files, {} of them new in the store.", name, snapshot.files.len(), stored_files));
+    if let Some(keep) = keep {
+        if prune(store, keep) == Outcome::Failure {
+            return Outcome::PartialFailure;
+        }
+    }
+    return Outcome::Success;
+}
+
+pub fn list_snapshots(store: &Path) -> Outcome {
+    let names = get_snapshot_names(store);
+    if names.is_empty() {
+        utils::print_info(format!("There are no snapshots in {}.", store.display()));
+        return Outcome::NothingToDo;
+    }
+    for name in names {
+        match load_snapshot(store, &name) {
+            Some(snapshot) => {
+                let size: u64 = snapshot.files.iter().map(|file| file.size).sum();
+                utils::print_info(format!("{}  {} files, {} bytes, from {}", name, snapshot.files.len(), size, snapshot.tray_folder.display()));
+            },
+            None => {
+                utils::print_warning(format!("{}  unreadable snapshot", name));
+            }
+        }
+    }
+    return Outcome::Success;
+}
+
+pub fn restore(tray_folder: &Path, store: &Path, name: &str, clean: bool) -> Outcome {
+    if !utils::is_plain_filename(name) {
+        utils::print_error(format!("\"{}\" isn't a snapshot name, the snapshots command lists them!", name));
+        return Outcome::Failure;
+    }
+    let snapshot = match load_snapshot(store, name) {
+        Some(snapshot) => snapshot,
+        None => {
+            utils::print_error(format!("Snapshot {} doesn't exists or can't be read!", name));
+            return Outcome::Failure;
+        }
+    };
+    let staging_folder = tray_folder.join(constants::STAGING_FOLDERNAME).join(format!("restore-{}", name));
+    let staged_paths = match stage_snapshot(store, &snapshot, &staging_folder) {
+        Ok(staged_paths) => staged_paths,
+        Err(e) => {
+            utils::print_error(format!("{}, the snapshot can't be restored!", e));
+            remove_staging_folder(tray_folder, &staging_folder);
+            return Outcome::Failure;
+        }
+    };
+    let extra_files: Vec<PathBuf> = utils::list_tray_folder(tray_folder).unwrap_or_default().into_iter().filter(|path| {
+        let filename = path.file_name().and_then(|filename| filename.to_str()).unwrap_or("");
+        return !snapshot.files.iter().any(|file| file.filename == filename);
+    }).collect();
+    let mut message = format!("Restoring snapshot {} will overwrite the matching files in {}", name, tray_folder.display());
+    if clean && !extra_files.is_empty() {
+        message.push_str(&format!(" and delete {} files that aren't part of it", extra_files.len()));
+    }
+    if !prompts::confirm(format!("{}. Do you want to continue?", message)) {
+        remove_staging_folder(tray_folder, &staging_folder);
+        return Outcome::Failure;
+    }
+    let target_paths: Vec<PathBuf> = snapshot.files.iter().map(|file| tray_folder.join(&file.filename)).collect();
+    let staged_files: Vec<(&Path, &Path)> = staged_paths.iter().zip(&target_paths).map(|(staged_path, path)| (staged_path.as_path(), path.as_path())).collect();
+    let mut changed_paths: Vec<&Path> = target_paths.iter().map(|path| path.as_path()).collect();
+    if clean {
+        changed_paths.extend(extra_files.iter().map(|path| path.as_path()));
+    }
+    if let Err(e) = journal::record(&format!("restore of snapshot {}", name), &changed_paths) {
+        utils::print_error(format!("{}, nothing was restored!", e));
+        remove_staging_folder(tray_folder, &staging_folder);
+        return Outcome::Failure;
+    }
+    let moved = utils::move_files_into_place(&staged_files);
+    remove_staging_folder(tray_folder, &staging_folder);
+    if let Err(e) = moved {
+        utils::print_error(format!("{}, the snapshot couldn't be restored!", e));
+        return Outcome::Failure;
+    }
+    let mut failed_files = 0;
+    for (file, target_path) in snapshot.files.iter().zip(&target_paths) {
+        let modified = UNIX_EPOCH + Duration::from_secs(file.modified);
+        if File::options().write(true).open(target_path).and_then(|target_file| target_file.set_modified(modified)).is_err() {
+            utils::print_warning(format!("Couldn't restore the modification date of {}.", file.filename));
+        }
+    }
+    if clean {
+        for path in &extra_files {
+            if fs::remove_file(path).is_err() {
+                utils::print_error(format!("Couldn't delete {}!", path.display()));
+                failed_files += 1;
+            }
+        }
+    }
+    if failed_files == 0 {
+        utils::print_success(format!("Snapshot {} restored, {} files written.", name, snapshot.files.len()));
+        return Outcome::Success;
+    }
+    utils::print_warning(format!("Snapshot {} restored with {} errors, the Tray folder may contain corrupted items!", name, failed_files));
+    return Outcome::PartialFailure;
+}
+
+/// Copies the stored files of the snapshot into a staging folder of the Tray folder and checks every copy, so a
+/// missing or damaged object is found before anything is overwritten.
+fn stage_snapshot(store: &Path, snapshot: &Snapshot, staging_folder: &Path) -> Result<Vec<PathBuf>, String> {
+    let _ = fs::remove_dir_all(staging_folder);
+    if fs::create_dir_all(staging_folder).is_err() {
+        return Err(format!("Couldn't create {}", staging_folder.display()));
+    }
+    let mut staged_paths: Vec<PathBuf> = Vec::new();
+    for file in &snapshot.files {
+        let staged_path = staging_folder.join(&file.filename);
+        let copied = match get_object_path(store, &file.hash) {
+            Some(object_path) => fs::copy(object_path, &staged_path).is_ok() && utils::hash_file(&staged_path).ok().as_ref() == Some(&file.hash),
+            None => false
+        };
+        if !copied {
+            return Err(format!("The stored copy of {} is missing or damaged", file.filename));
+        }
+        staged_paths.push(staged_path);
+    }
+    return Ok(staged_paths);
+}
+
+fn remove_staging_folder(tray_folder: &Path, staging_folder: &Path) {
+    let _ = fs::remove_dir_all(staging_folder);
+    let _ = fs::remove_dir(tray_folder.join(constants::STAGING_FOLDERNAME));
+}
+
+/// Keeps the newest snapshots and deletes the stored files no remaining snapshot refers to.
+pub fn prune(store: &Path, keep: usize) -> Outcome {
+    let names = get_snapshot_names(store);
+    let snapshots_folder = store.join(constants::BACKUP_SNAPSHOTS_FOLDERNAME);
+    if keep == 0 && !names.is_empty() && !prompts::confirm(format!("Keeping 0 snapshots deletes all {} snapshots of the store. Do you want to continue?", names.len())) {
+        return Outcome::Failure;
+    }
+    let removed = names.len().saturating_sub(keep);
+    for name in &names[..removed] {
+        if fs::remove_file(snapshots_folder.join(format!("{}.toml", name))).is_err() {
+            utils::print_error(format!("Couldn't remove snapshot {}, nothing else will be pruned!", name));
+            return Outcome::Failure;
+        }
+    }
+    let mut referenced_hashes: HashSet<String> = HashSet::new();
+    for name in &names[removed..] {
+        match load_snapshot(store, name) {
+            Some(snapshot) => {
+                referenced_hashes.extend(snapshot.files.into_iter().map(|file| file.hash));
+            },
+            None => {
+                utils::print_error(format!("Snapshot {} can't be read, stored files won't be pruned!", name));
+                return Outcome::Failure;
+            }
+        }
+    }
+    let mut removed_objects = 0;
+    if let Ok(prefixes) = fs::read_dir(store.join(constants::BACKUP_OBJECTS_FOLDERNAME)) {
+        for prefix in prefixes.flatten() {
+            if let Ok(objects) = fs::read_dir(prefix.path()) {
+                for object in objects.flatten() {
+                    let hash = object.file_name().to_string_lossy().to_string();
+                    if !referenced_hashes.contains(&hash) && fs::remove_file(object.path()).is_ok() {
+                        removed_objects += 1;
+                    }
+                }
+            }
+            let _ = fs::remove_dir(prefix.path());
+        }
+    }
+    utils::print_success(format!("Pruned {} snapshots and {} stored files.", removed, removed_objects));
+    return Outcome::Success;
+}
+
+/// A snapshot whose file names aren't plain names can't be used, restoring it would write outside the Tray folder.
+pub fn load_snapshot(store: &Path, name: &str) -> Option<Snapshot> {
+    if !utils::is_plain_filename(name) {
+        return None;
+    }
+    let path = store.join(constants::BACKUP_SNAPSHOTS_FOLDERNAME).join(format!("{}.toml", name));
+    let content = fs::read_to_string(path).ok()?;
+    let snapshot: Snapshot = toml::from_str(&content).ok()?;
+    if !snapshot.files.iter().all(|file| utils::is_plain_filename(&file.filename)) {
+        return None;
+    }
+    return Some(snapshot);
+}
+
+/// Snapshot names are timestamps, so sorting them also sorts them from oldest to newest.
+pub fn get_snapshot_names(store: &Path) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    if let Ok(items) = fs::read_dir(store.join(constants::BACKUP_SNAPSHOTS_FOLDERNAME)) {
+        for item in items.flatten() {
+            let path = item.path();
+            if path.extension().and_then(|extension| extension.to_str()) == Some("toml") {
+                if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                    names.push(String::from(name));
+                }
+            }
+        }
+    }
+    names.sort();
+    return names;
+}
+
+/// Copies the file into the store unless a file with the same content is already there. The file is hashed while
+/// it is copied, so the object is named after the bytes that really reached the store even if the file changes meanwhile.
+fn store_object(store: &Path, path: &Path) -> Option<(String, bool)> {
+    let objects_folder = store.join(constants::BACKUP_OBJECTS_FOLDERNAME);
+    fs::create_dir_all(&objects_folder).ok()?;
+    let temporary_path = objects_folder.join(format!("{}.tmp", std::process::id()));
+    let copied = File::open(path).and_then(|mut file| {
+        let mut writer = HashingWriter { file: File::create(&temporary_path)?, hasher: Sha256::new() };
+        io::copy(&mut file, &mut writer)?;
+        writer.file.sync_all()?;
+        return Ok(hex::encode(writer.hasher.finalize()));
+    });
+    let hash = match copied {
+        Ok(hash) => hash,
+        Err(_) => {
+            let _ = fs::remove_file(&temporary_path);
+            return None;
+        }
+    };
+    let object_path = match get_object_path(store, &hash) {
+        Some(object_path) => object_path,
+        None => {
+            let _ = fs::remove_file(&temporary_path);
+            return None;
+        }
+    };
+    if object_path.exists() {
+        let _ = fs::remove_file(&temporary_path);
+        return Some((hash, false));
+    }
+    match fs::create_dir_all(objects_folder.join(&hash[..2])).and_then(|_| fs::rename(&temporary_path, &object_path)) {
+        Ok(_) => {
+            return Some((hash, true));
+        },
+        Err(e) => {
+            let _ = fs::remove_file(&temporary_path);
+            if e.kind() == ErrorKind::AlreadyExists {
+                return Some((hash, false));
+            }
+            return None;
+        }
+    }
+}
+
+struct HashingWriter {
+    file: File,
+    hasher: Sha256,
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let count = self.file.write(buffer)?;
+        self.hasher.update(&buffer[..count]);
+        return Ok(count);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.file.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn store_object_names_the_copy_after_its_content() {
+        let folder = env::temp_dir().join(format!("{}-store-test-{}", constants::APPLICATION_NAME, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        let store = folder.join("store");
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("0x00000001!0x00aa12f12d6202c5.trayitem");
+        fs::write(&path, "trayitem").unwrap();
+        let (hash, is_new) = store_object(&store, &path).unwrap();
+        assert_eq!(hash, utils::hash_bytes(b"trayitem"));
+        assert!(is_new);
+        assert_eq!(fs::read(get_object_path(&store, &hash).unwrap()).unwrap(), b"trayitem");
+        assert_eq!(store_object(&store, &path).unwrap(), (hash, false));
+        let objects: Vec<String> = fs::read_dir(store.join(constants::BACKUP_OBJECTS_FOLDERNAME)).unwrap().flatten().map(|item| item.file_name().to_string_lossy().to_string()).collect();
+        assert_eq!(objects, vec![utils::hash_bytes(b"trayitem")[..2].to_string()]);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
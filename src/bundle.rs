@@ -0,0 +1,474 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::Local;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::anonymize::Anonymizer;
+use crate::progress::PackProgress;
+use crate::tray_files::{TrayItem, TrayItemFile};
+use crate::utils::Outcome;
+use crate::{constants, custom_content, journal, packing, prompts, signing, utils};
+
+/// Describes everything a `.s4pack` bundle holds, so it can be checked before anything is written to the Tray folder.
+#[derive(Deserialize, Serialize)]
+pub struct Manifest {
+    pub format_version: u32,
+    pub created: String,
+    pub items: Vec<ManifestItem>,
+}
+
+/// Ids are written as hexadecimal strings since TOML integers can't hold every 64 bit value.
+#[derive(Deserialize, Serialize)]
+pub struct ManifestItem {
+    pub id: String,
+    pub file_type: u32,
+    pub name: String,
+    pub creator_name: String,
+    pub creator_id: String,
+    pub folder: String,
+    pub files: Vec<ManifestFile>,
+    #[serde(default)]
+    pub custom_content: Vec<ManifestFile>,
+}
+
+/// `name` is where the file gets installed: a Tray file name, or a path inside the Mods folder for custom content.
+#[derive(Deserialize, Serialize)]
+pub struct ManifestFile {
+    pub name: String,
+    pub path: String,
+    pub hash: String,
+    pub size: u64,
+}
+
+pub struct BundleItem<'a> {
+    pub tray_item: &'a TrayItem,
+    pub relative_path: PathBuf,
+    pub companion_files: Vec<&'a TrayItemFile>,
+    pub custom_content_files: Vec<(&'a Path, PathBuf)>,
+}
+
+/// A file of the bundle checked against the manifest and ready to be written.
+pub(crate) struct BundleFile {
+    pub(crate) target_path: PathBuf,
+    pub(crate) content: Vec<u8>,
+}
+
+/// Returns false when the bundle couldn't be written, its items are then missing from the output.
+pub fn write_bundle(bundle_path: &Path, items: &[BundleItem], anonymizer: Option<&Anonymizer>, signing_key: Option<&SigningKey>, progress: &PackProgress) -> bool {
+    if bundle_path.exists() {
+        utils::print_error(format!("Bundle {} already exists!", bundle_path.display()));
+        return false;
+    }
+    let mut temporary_path = bundle_path.as_os_str().to_os_string();
+    temporary_path.push(".tmp");
+    let temporary_path = PathBuf::from(temporary_path);
+    let bundle_file = match File::create(&temporary_path) {
+        Ok(bundle_file) => bundle_file,
+        Err(_) => {
+            utils::print_error(format!("Unable to create bundle {}!", bundle_path.display()));
+            return false;
+        }
+    };
+    let mut archive = ZipWriter::new(bundle_file);
+    let mut manifest = Manifest {
+        format_version: constants::BUNDLE_FORMAT_VERSION,
+        created: Local::now().to_rfc3339(),
+        items: Vec::new(),
+    };
+    // Items are read and anonymized in parallel a few at a time, the archive itself can only be written in order.
+    let batch_size = rayon::current_num_threads();
+    for (batch_index, batch) in items.chunks(batch_size).enumerate() {
+        let batch_files = utils::parallel_map(batch, |item| read_item_files(item, anonymizer));
+        for (position, (item, files)) in batch.iter().zip(batch_files).enumerate() {
+            let tray_item = item.tray_item;
+            let manifest_tray_item = match anonymizer {
+                Some(anonymizer) => anonymizer.anonymize_item(tray_item),
+                None => tray_item.clone()
+            };
+            let folder = to_entry_name(&item.relative_path);
+            let mut manifest_item = ManifestItem {
+                id: format!("0x{:016x}", tray_item.id),
+                file_type: tray_item.file_type,
+                name: tray_item.name.clone(),
+                creator_name: manifest_tray_item.creator_name,
+                creator_id: format!("0x{:016x}", manifest_tray_item.creator_id),
+                folder: folder.clone(),
+                files: Vec::new(),
+                custom_content: Vec::new(),
+            };
+            // Every file is read before anything is added, so an item missing a file is left out instead of half written.
+            if let Some((name, _, _)) = files.iter().find(|(_, _, content)| content.is_err()) {
+                utils::print_error(format!("[{}] Couldn't read {}!", tray_item.name, name));
+                utils::print_warning(format!("[{}] The whole gallery item will be skipped!", tray_item.name));
+                progress.finish_item(tray_item, false);
+                continue;
+            }
+            for (name, is_custom_content, content) in files {
+                let entry_folder = match is_custom_content {
+                    true => join_entry_name(&folder, constants::MODS_FOLDER_NAME),
+                    false => folder.clone()
+                };
+                match content.ok().and_then(|content| add_file(&mut archive, &content, &name, &entry_folder)) {
+                    Some(manifest_file) => {
+                        progress.add_bytes(manifest_file.size);
+                        if is_custom_content {
+                            manifest_item.custom_content.push(manifest_file);
+                        } else {
+                            manifest_item.files.push(manifest_file);
+                        }
+                        utils::print_detail(format!("[{}] {} added to the bundle.", tray_item.name, name));
+                    },
+                    None => {
+                        // The archive can't be trusted after a failed write, so the whole bundle is given up.
+                        utils::print_error(format!("[{}] Couldn't add {} to the bundle!", tray_item.name, name));
+                        drop(archive);
+                        let _ = fs::remove_file(&temporary_path);
+                        for item in &items[batch_index * batch_size + position..] {
+                            progress.finish_item(item.tray_item, false);
+                        }
+                        utils::print_error(format!("Couldn't write bundle {}!", bundle_path.display()));
+                        return false;
+                    }
+                }
+            }
+            manifest.items.push(manifest_item);
+            progress.finish_item(tray_item, true);
+        }
+    }
+    let written = toml::to_string(&manifest).map_err(|_| ()).and_then(|content| {
+        archive.start_file(constants::BUNDLE_MANIFEST_FILENAME, SimpleFileOptions::default()).map_err(|_| ())?;
+        archive.write_all(content.as_bytes()).map_err(|_| ())?;
+        if let Some(signing_key) = signing_key {
+            let signature = toml::to_string(&signing::sign(content.as_bytes(), signing_key)).map_err(|_| ())?;
+            archive.start_file(constants::BUNDLE_SIGNATURE_FILENAME, SimpleFileOptions::default()).map_err(|_| ())?;
+            archive.write_all(signature.as_bytes()).map_err(|_| ())?;
+        }
+        let bundle_file = archive.finish().map_err(|_| ())?;
+        bundle_file.sync_all().map_err(|_| ())?;
+        return fs::rename(&temporary_path, bundle_path).map_err(|_| ());
+    });
+    match written {
+        Ok(_) => {
+            utils::print_success(format!("Bundle {} written with {} gallery items.", bundle_path.display(), manifest.items.len()));
+            return true;
+        },
+        Err(_) => {
+            utils::print_error(format!("Couldn't write bundle {}!", bundle_path.display()));
+            let _ = fs::remove_file(&temporary_path);
+            return false;
+        }
+    }
+}
+
+/// Name, whether it is custom content, and content of every file of the item as it goes in the bundle.
+fn read_item_files(item: &BundleItem, anonymizer: Option<&Anonymizer>) -> Vec<(String, bool, io::Result<Vec<u8>>)> {
+    let tray_item = item.tray_item;
+    let mut files: Vec<(String, bool, io::Result<Vec<u8>>)> = vec![(tray_item.filename.clone(), false, packing::read_item_file(tray_item, &tray_item.path, anonymizer))];
+    for companion_file in &item.companion_files {
+        files.push((companion_file.filename.clone(), false, packing::read_item_file(tray_item, &companion_file.path, anonymizer)));
+    }
+    for (package_path, package_relative_path) in &item.custom_content_files {
+        let name = to_entry_name(package_relative_path.strip_prefix(constants::MODS_FOLDER_NAME).unwrap_or(package_relative_path));
+        files.push((name, true, fs::read(package_path)));
+    }
+    return files;
+}
+
+/// Installs a bundle, every file is checked against the manifest before the Tray and Mods folders are touched.
+pub fn unpack(bundle_path: &Path, tray_folder: &Path, mods_override: Option<&Path>, trusted_keys: &[VerifyingKey], require_signature: bool) -> Outcome {
+    let files = match read_bundle(bundle_path, tray_folder, mods_override, trusted_keys, require_signature) {
+        Ok(files) => files,
+        Err(e) => {
+            utils::print_error(format!("{}, nothing was installed!", e));
+            return Outcome::Failure;
+        }
+    };
+    let mut changed_files: Vec<&BundleFile> = Vec::new();
+    let mut overwritten_files = 0;
+    for file in &files {
+        match fs::read(&file.target_path) {
+            Ok(existing_content) => {
+                if existing_content != file.content {
+                    changed_files.push(file);
+                    overwritten_files += 1;
+                }
+            },
+            Err(_) => {
+                changed_files.push(file);
+            }
+        }
+    }
+    if changed_files.is_empty() {
+        utils::print_success(String::from("Everything in the bundle is already installed."));
+        return Outcome::NothingToDo;
+    }
+    if overwritten_files > 0 && !prompts::confirm(format!("{} files of the bundle will overwrite different files with the same name. Do you want to continue?", overwritten_files)) {
+        return Outcome::Failure;
+    }
+    let staged_files: Vec<(&Path, &[u8])> = changed_files.iter().map(|file| (file.target_path.as_path(), file.content.as_slice())).collect();
+    let target_paths: Vec<&Path> = staged_files.iter().map(|(path, _)| *path).collect();
+    if let Err(e) = journal::record(&format!("unpack {}", bundle_path.display()), &target_paths) {
+        utils::print_error(format!("{}, nothing was installed!", e));
+        return Outcome::Failure;
+    }
+    match utils::write_files_atomically(&staged_files) {
+        Ok(_) => {
+            utils::print_success(format!("Bundle {} installed, {} files written.", bundle_path.display(), changed_files.len()));
+            return Outcome::Success;
+        },
+        Err(e) => {
+            utils::print_error(format!("{}, the bundle couldn't be installed!", e));
+            return Outcome::Failure;
+        }
+    }
+}
+
+pub fn parse_manifest(content: &[u8]) -> Result<Manifest, String> {
+    let content = String::from_utf8(content.to_vec()).map_err(|_| String::from("The bundle manifest isn't valid text"))?;
+    let manifest: Manifest = toml::from_str(&content).map_err(|e| format!("The bundle manifest is invalid: {}", e.message()))?;
+    if manifest.format_version > constants::BUNDLE_FORMAT_VERSION {
+        return Err(format!("The bundle uses format version {}, this version only reads up to {}", manifest.format_version, constants::BUNDLE_FORMAT_VERSION));
+    }
+    return Ok(manifest);
+}
+
+fn check_signature(archive: &mut ZipArchive<File>, manifest_content: &[u8], trusted_keys: &[VerifyingKey], require_signature: bool) -> Result<(), String> {
+    let signature_content = read_entry(archive, constants::BUNDLE_SIGNATURE_FILENAME, constants::BUNDLE_SIGNATURE_MAX_SIZE)?;
+    return signing::check(manifest_content, signature_content.as_deref(), trusted_keys, require_signature);
+}
+
+pub(crate) fn read_bundle(bundle_path: &Path, tray_folder: &Path, mods_override: Option<&Path>, trusted_keys: &[VerifyingKey], require_signature: bool) -> Result<Vec<BundleFile>, String> {
+    let bundle_file = File::open(bundle_path).map_err(|_| format!("Couldn't open bundle {}", bundle_path.display()))?;
+    let mut archive = ZipArchive::new(bundle_file).map_err(|_| format!("{} isn't a valid bundle", bundle_path.display()))?;
+    let manifest_content = read_entry(&mut archive, constants::BUNDLE_MANIFEST_FILENAME, constants::BUNDLE_MANIFEST_MAX_SIZE)?.ok_or(String::from("The bundle has no manifest"))?;
+    check_signature(&mut archive, &manifest_content, trusted_keys, require_signature)?;
+    let manifest = parse_manifest(&manifest_content)?;
+    let mut mods_folder: Option<PathBuf> = None;
+    if manifest.items.iter().any(|item| !item.custom_content.is_empty()) {
+        mods_folder = custom_content::get_mods_folder(tray_folder, mods_override);
+        if mods_folder.is_none() {
+            return Err(String::from("The bundle contains custom content but there is no Mods folder to install it"));
+        }
+    }
+    let mut listed_paths: HashSet<&str> = HashSet::new();
+    listed_paths.insert(constants::BUNDLE_MANIFEST_FILENAME);
+    listed_paths.insert(constants::BUNDLE_SIGNATURE_FILENAME);
+    let mut files: Vec<BundleFile> = Vec::new();
+    for item in &manifest.items {
+        for manifest_file in &item.files {
+            if !is_tray_filename(&manifest_file.name) {
+                return Err(format!("[{}] {} isn't a Tray file name", item.name, manifest_file.name));
+            }
+            let content = read_checked_entry(&mut archive, manifest_file).map_err(|e| format!("[{}] {}", item.name, e))?;
+            files.push(BundleFile { target_path: tray_folder.join(&manifest_file.name), content });
+            listed_paths.insert(&manifest_file.path);
+        }
+        for manifest_file in &item.custom_content {
+            if !is_package_path(&manifest_file.name) {
+                return Err(format!("[{}] {} isn't a package path inside the Mods folder", item.name, manifest_file.name));
+            }
+            let content = read_checked_entry(&mut archive, manifest_file).map_err(|e| format!("[{}] {}", item.name, e))?;
+            if let Some(mods_folder) = &mods_folder {
+                files.push(BundleFile { target_path: mods_folder.join(&manifest_file.name), content });
+            }
+            listed_paths.insert(&manifest_file.path);
+        }
+    }
+    for entry_name in archive.file_names() {
+        if !listed_paths.contains(entry_name) && !entry_name.ends_with('/') {
+            return Err(format!("The bundle contains {} which isn't listed in its manifest", entry_name));
+        }
+    }
+    return Ok(files);
+}
+
+/// The size is checked before reading, a huge entry is refused without being decompressed.
+fn read_checked_entry(archive: &mut ZipArchive<File>, manifest_file: &ManifestFile) -> Result<Vec<u8>, String> {
+    let damaged = format!("{} doesn't match its checksum, the bundle is damaged", manifest_file.path);
+    let mut entry = archive.by_name(&manifest_file.path).map_err(|_| format!("{} is missing from the bundle", manifest_file.path))?;
+    if entry.size() != manifest_file.size {
+        return Err(damaged);
+    }
+    let content = read_limited(&mut entry, manifest_file.size).ok_or(damaged.clone())?;
+    if content.len() as u64 != manifest_file.size || utils::hash_bytes(&content) != manifest_file.hash {
+        return Err(damaged);
+    }
+    return Ok(content);
+}
+
+/// None when the bundle has no such entry, an error when it is larger than `max_size`.
+fn read_entry(archive: &mut ZipArchive<File>, name: &str, max_size: u64) -> Result<Option<Vec<u8>>, String> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(_) => {
+            return Ok(None);
+        }
+    };
+    let too_large = format!("{} is unreadable or too large, the bundle is damaged", name);
+    if entry.size() > max_size {
+        return Err(too_large);
+    }
+    return read_limited(&mut entry, max_size).map(Some).ok_or(too_large);
+}
+
+/// The sizes in the zip headers come from the sender too, so reading stops one byte past the expected size.
+fn read_limited<R: Read>(reader: R, max_size: u64) -> Option<Vec<u8>> {
+    let mut content: Vec<u8> = Vec::new();
+    reader.take(max_size + 1).read_to_end(&mut content).ok()?;
+    if content.len() as u64 > max_size {
+        return None;
+    }
+    return Some(content);
+}
+
+fn add_file(archive: &mut ZipWriter<File>, content: &[u8], name: &str, folder: &str) -> Option<ManifestFile> {
+    let entry_name = join_entry_name(folder, name);
+    archive.start_file(entry_name.as_str(), SimpleFileOptions::default()).ok()?;
+    archive.write_all(content).ok()?;
+    return Some(ManifestFile {
+        name: String::from(name),
+        path: entry_name,
+        hash: utils::hash_bytes(content),
+        size: content.len() as u64,
+    });
+}
+
+/// Entries of an item without a folder sit at the root of the archive, never under an absolute `/` path.
+fn join_entry_name(folder: &str, name: &str) -> String {
+    if folder.is_empty() {
+        return String::from(name);
+    }
+    return format!("{}/{}", folder, name);
+}
+
+fn to_entry_name(path: &Path) -> String {
+    return path.iter().map(|component| component.to_string_lossy()).collect::<Vec<_>>().join("/");
+}
+
+/// Bundles come from other people, so names that could escape the target folder are refused. Drive prefixes and
+/// backslashes only mean something on Windows, they are refused everywhere so a bundle unpacks the same on every system.
+fn is_tray_filename(name: &str) -> bool {
+    if name.contains(['\\', ':']) {
+        return false;
+    }
+    let path = Path::new(name);
+    if path.file_name().and_then(|filename| filename.to_str()) != Some(name) {
+        return false;
+    }
+    return path.extension().and_then(|extension| extension.to_str())
+        .map(|extension| constants::TRAY_FILE_EXTENSIONS.contains(&extension))
+        .unwrap_or(false);
+}
+
+fn is_package_path(name: &str) -> bool {
+    if name.contains(['\\', ':']) {
+        return false;
+    }
+    let path = Path::new(name);
+    if !path.components().all(|component| matches!(component, Component::Normal(_))) {
+        return false;
+    }
+    return path.extension().and_then(|extension| extension.to_str()) == Some(constants::PACKAGE_EXT);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn is_tray_filename_refuses_paths() {
+        assert!(is_tray_filename("0x00000001!0x00aa12f12d6202c5.trayitem"));
+        assert!(is_tray_filename("0x00000000!0x00aa12f12d6202c6.sgi"));
+        for name in ["../0x00000001!0x00aa12f12d6202c5.trayitem", "Villa Rosa/0x00000001!0x00aa12f12d6202c5.trayitem",
+            "/tmp/0x00000001!0x00aa12f12d6202c5.trayitem", "C:0x00000001!0x00aa12f12d6202c5.trayitem",
+            "C:\\Tray\\0x00000001!0x00aa12f12d6202c5.trayitem", "..", "", "0x00000001!0x00aa12f12d6202c5.package", "setup.exe"] {
+            assert!(!is_tray_filename(name), "{} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn is_package_path_refuses_paths_leaving_the_mods_folder() {
+        assert!(is_package_path("chair.package"));
+        assert!(is_package_path("Creator/Kitchen/chair.package"));
+        for name in ["../chair.package", "Creator/../../chair.package", "./chair.package", "/home/simmer/chair.package",
+            "C:/Mods/chair.package", "C:\\Mods\\chair.package", "C:chair.package", "\\\\server\\share\\chair.package",
+            "Creator/script.ts4script", "Creator/", ""] {
+            assert!(!is_package_path(name), "{} was accepted", name);
+        }
+    }
+
+    /// Writes a bundle holding one item with `files`, plus `unlisted_files` left out of the manifest.
+    fn write_test_bundle(name: &str, files: &[(&str, &[u8])], unlisted_files: &[(&str, &[u8])], change_manifest: fn(&mut Manifest)) -> (PathBuf, PathBuf) {
+        let folder = env::temp_dir().join(format!("{}-bundle-test-{}-{}", constants::APPLICATION_NAME, name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        let tray_folder = folder.join("Tray");
+        fs::create_dir_all(&tray_folder).unwrap();
+        let bundle_path = folder.join("gallery.s4pack");
+        let mut archive = ZipWriter::new(File::create(&bundle_path).unwrap());
+        let manifest_files: Vec<ManifestFile> = files.iter().map(|(name, content)| add_file(&mut archive, content, name, "Villa Rosa").unwrap()).collect();
+        for (name, content) in unlisted_files {
+            add_file(&mut archive, content, name, "Villa Rosa").unwrap();
+        }
+        let mut manifest = Manifest {
+            format_version: constants::BUNDLE_FORMAT_VERSION,
+            created: String::new(),
+            items: vec![ManifestItem {
+                id: String::from("0x00aa12f12d6202c5"),
+                file_type: constants::HOUSEHOLD_TRAYITEM_TYPE,
+                name: String::from("Villa Rosa"),
+                creator_name: String::from("simmer42"),
+                creator_id: String::from("0x0000123456789abc"),
+                folder: String::from("Villa Rosa"),
+                files: manifest_files,
+                custom_content: Vec::new(),
+            }],
+        };
+        change_manifest(&mut manifest);
+        archive.start_file(constants::BUNDLE_MANIFEST_FILENAME, SimpleFileOptions::default()).unwrap();
+        archive.write_all(toml::to_string(&manifest).unwrap().as_bytes()).unwrap();
+        archive.finish().unwrap();
+        return (bundle_path, tray_folder);
+    }
+
+    fn read_test_bundle(name: &str, unlisted_files: &[(&str, &[u8])], change_manifest: fn(&mut Manifest)) -> Result<Vec<BundleFile>, String> {
+        let files: [(&str, &[u8]); 2] = [("0x00000001!0x00aa12f12d6202c5.trayitem", b"trayitem"), ("0x00000000!0x00aa12f12d6202c6.sgi", b"thumbnail")];
+        let (bundle_path, tray_folder) = write_test_bundle(name, &files, unlisted_files, change_manifest);
+        let result = read_bundle(&bundle_path, &tray_folder, None, &[], false);
+        fs::remove_dir_all(bundle_path.parent().unwrap()).unwrap();
+        return result;
+    }
+
+    #[test]
+    fn read_bundle_accepts_listed_files() {
+        let files = read_test_bundle("valid", &[], |_| {}).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files[0].target_path.ends_with("Tray/0x00000001!0x00aa12f12d6202c5.trayitem"));
+        assert_eq!(files[0].content, b"trayitem");
+        assert_eq!(files[1].content, b"thumbnail");
+    }
+
+    #[test]
+    fn read_bundle_refuses_unlisted_entries() {
+        let error = read_test_bundle("unlisted", &[("0x00000000!0x00aa12f12d6202c7.sgi", b"extra")], |_| {}).err().unwrap();
+        assert!(error.contains("isn't listed in its manifest"), "{}", error);
+    }
+
+    #[test]
+    fn read_bundle_refuses_checksum_and_size_mismatches() {
+        let error = read_test_bundle("checksum", &[], |manifest| manifest.items[0].files[1].hash = utils::hash_bytes(b"something else")).err().unwrap();
+        assert!(error.contains("doesn't match its checksum"), "{}", error);
+        let error = read_test_bundle("size", &[], |manifest| manifest.items[0].files[0].size = 3).err().unwrap();
+        assert!(error.contains("doesn't match its checksum"), "{}", error);
+        let error = read_test_bundle("name", &[], |manifest| manifest.items[0].files[0].name = String::from("../0x00000001!0x00aa12f12d6202c5.trayitem")).err().unwrap();
+        assert!(error.contains("isn't a Tray file name"), "{}", error);
+    }
+}
@@ -0,0 +1,416 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+
+use ed25519_dalek::VerifyingKey;
+
+use crate::{constants, filters, inspect, signing};
+
+/// Packs the gallery items found in the Sims 4 Tray folder into the output folder.
+///
+/// Defaults for every option can be stored in config.toml inside the platform configuration folder,
+/// options given on the command line always win. Its [filters] table only applies to pack and watch.
+#[derive(Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true, after_help = constants::EXIT_CODES_HELP)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Configuration file to read instead of the default one.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Tray folder to use, instead of searching the documents folder and Wine/Proton prefixes.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub tray: Option<PathBuf>,
+
+    /// Mods folder to search for custom content [default: "Mods" next to the Tray folder].
+    #[arg(long, value_name = "PATH", global = true)]
+    pub mods: Option<PathBuf>,
+
+    /// Don't wait for a key press before exiting.
+    #[arg(long, global = true, overrides_with = "pause")]
+    pub no_pause: bool,
+
+    /// Wait for a key press before exiting, even when the configuration file turns it off.
+    #[arg(long, global = true, overrides_with = "no_pause")]
+    pub pause: bool,
+
+    /// Never ask anything: confirmations are answered yes and lists keep their default choice.
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+
+    /// Show more details, twice to trace everything that is done.
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Only show warnings and errors, twice to only show errors.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub quiet: u8,
+
+    /// Also write every message, with debug details, to this file.
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub pack: PackArgs,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Pack the gallery items into the output folder (default when no command is given).
+    Pack(PackArgs),
+    /// Take a snapshot of the whole Tray folder into the backup store.
+    Backup(BackupArgs),
+    /// List the snapshots in the backup store.
+    Snapshots(StoreArgs),
+    /// Restore a snapshot into the Tray folder.
+    Restore(RestoreArgs),
+    /// Remove the oldest snapshots and the files only they were using.
+    Prune(PruneArgs),
+    /// Compare two Tray folders, pack folders or snapshots item by item.
+    Diff(DiffArgs),
+    /// List the custom content packages of the Mods folder every item needs.
+    Deps(DepsArgs),
+    /// Check a .s4pack bundle and install its items into the Tray folder.
+    Unpack(UnpackArgs),
+    /// Create the local key used to sign bundles and print its public key.
+    Keygen(KeygenArgs),
+    /// Change the name, creator or description hashtags of many items at once.
+    Edit(EditArgs),
+    /// Roll back the last install, restore or edit of the Tray folder.
+    Undo(UndoArgs),
+    /// Keep running and pack every item again each time the game saves it, without emptying the output folder.
+    /// With the s4pack format, every item gets its own bundle.
+    Watch(WatchArgs),
+    /// Install the item folders of a packed output folder back into the Tray folder.
+    RestoreOutput(RestoreOutputArgs),
+    /// Find items saved several times and move all but the newest copy out of the Tray folder.
+    Duplicates(DuplicatesArgs),
+    /// Show item counts, disk usage by file kind, and the largest and oldest items.
+    Stats(StatsArgs),
+    /// Show the file names, decoded trayitem fields and a hex dump of one item.
+    Inspect(InspectArgs),
+    /// Print a file as a tree of protobuf fields without knowing its schema, to help decoding unknown fields.
+    Dump(DumpArgs),
+}
+
+#[derive(Args)]
+pub struct PackArgs {
+    /// Output folder, it is emptied on every run [default: ./output].
+    #[arg(long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Path of every item folder inside the output folder [default: "{type}/{name} ({id})"].
+    /// Available fields: {type}, {name}, {creator}, {creator_id}, {id} and {date}.
+    #[arg(long, value_name = "TEMPLATE")]
+    pub name_template: Option<String>,
+
+    /// Write all the items into one .s4pack bundle, or every item as a plain folder or a zip archive [default: s4pack].
+    #[arg(long, value_enum)]
+    pub format: Option<OutputFormat>,
+
+    /// Choose the items to pack from an interactive list.
+    #[arg(short, long)]
+    pub pick: bool,
+
+    /// Copy the custom content packages every item needs into a Mods folder inside the item.
+    #[arg(long, overrides_with = "no_include_cc")]
+    pub include_cc: bool,
+
+    /// Don't copy custom content, even when the configuration file turns it on.
+    #[arg(long, overrides_with = "include_cc")]
+    pub no_include_cc: bool,
+
+    /// Replace the creator name and id inside the packed files, for sharing items publicly.
+    #[arg(long, overrides_with = "no_anonymize")]
+    pub anonymize: bool,
+
+    /// Keep the creator, even when the configuration file turns anonymizing on.
+    #[arg(long, overrides_with = "anonymize")]
+    pub no_anonymize: bool,
+
+    /// Creator name written when anonymizing [default: "Anonymous"].
+    #[arg(long, value_name = "NAME")]
+    pub anonymous_name: Option<String>,
+
+    /// Hexadecimal creator id written when anonymizing [default: 0].
+    #[arg(long, value_name = "ID", value_parser = filters::parse_id)]
+    pub anonymous_id: Option<u64>,
+
+    /// Sign the .s4pack bundle with the local key created by the keygen command.
+    #[arg(long, overrides_with = "no_sign")]
+    pub sign: bool,
+
+    /// Don't sign the bundle, even when the configuration file turns signing on.
+    #[arg(long, overrides_with = "sign")]
+    pub no_sign: bool,
+
+    #[command(flatten)]
+    pub filters: FilterArgs,
+}
+
+#[derive(Args)]
+pub struct StoreArgs {
+    /// Backup store folder [default: "backups" inside the platform data folder].
+    #[arg(long, value_name = "PATH")]
+    pub store: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct BackupArgs {
+    #[command(flatten)]
+    pub store: StoreArgs,
+
+    /// Prune the store down to this many snapshots after the backup, 0 asks before deleting them all.
+    #[arg(long, value_name = "COUNT")]
+    pub keep: Option<usize>,
+}
+
+#[derive(Args)]
+pub struct RestoreArgs {
+    /// Name of the snapshot to restore, as listed by the snapshots command.
+    pub snapshot: String,
+
+    #[command(flatten)]
+    pub store: StoreArgs,
+
+    /// Also delete the files of the Tray folder that aren't part of the snapshot.
+    #[arg(long)]
+    pub clean: bool,
+}
+
+#[derive(Args)]
+pub struct PruneArgs {
+    #[command(flatten)]
+    pub store: StoreArgs,
+
+    /// Number of snapshots to keep, the newest ones are kept. 0 asks before deleting them all.
+    #[arg(long, value_name = "COUNT")]
+    pub keep: Option<usize>,
+}
+
+#[derive(Args)]
+pub struct FilterArgs {
+    /// Only pack items of this type (repeatable).
+    #[arg(long = "type", value_name = "TYPE")]
+    pub item_type: Vec<ItemType>,
+
+    /// Only pack items whose name matches this glob, or this regex when prefixed with "re:".
+    #[arg(long, value_name = "PATTERN")]
+    pub name: Option<String>,
+
+    /// Only pack items made by this creator, case insensitive (repeatable).
+    #[arg(long)]
+    pub creator: Vec<String>,
+
+    /// Only pack the item with this hexadecimal id (repeatable).
+    #[arg(long, value_parser = filters::parse_id)]
+    pub id: Vec<u64>,
+
+    /// Only pack items saved on or after this date (YYYY-MM-DD or RFC 3339).
+    #[arg(long, value_name = "DATE", value_parser = filters::parse_date)]
+    pub modified_since: Option<SystemTime>,
+}
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// Old side: a folder path or a snapshot name.
+    pub old: String,
+
+    /// New side: a folder path or a snapshot name.
+    pub new: String,
+
+    #[command(flatten)]
+    pub store: StoreArgs,
+}
+
+#[derive(Args)]
+pub struct DepsArgs {
+    #[command(flatten)]
+    pub filters: FilterArgs,
+}
+
+#[derive(Args)]
+pub struct UnpackArgs {
+    /// Path of the .s4pack bundle.
+    pub bundle: PathBuf,
+
+    /// Public key of a sender whose signed bundles are trusted (repeatable).
+    #[arg(long, value_name = "KEY", value_parser = signing::parse_public_key)]
+    pub trusted_key: Vec<VerifyingKey>,
+
+    /// Refuse bundles that aren't signed by a trusted key.
+    #[arg(long, overrides_with = "no_require_signature")]
+    pub require_signature: bool,
+
+    /// Accept unsigned bundles, even when the configuration file requires a signature.
+    #[arg(long, overrides_with = "require_signature")]
+    pub no_require_signature: bool,
+}
+
+#[derive(Args)]
+pub struct EditArgs {
+    /// Folder holding the items to edit, a packed output folder for example [default: the Tray folder].
+    #[arg(long, value_name = "PATH")]
+    pub path: Option<PathBuf>,
+
+    /// New creator name.
+    #[arg(long, value_name = "NAME")]
+    pub creator_name: Option<String>,
+
+    /// Hashtag to add at the end of the description (repeatable).
+    #[arg(long, value_name = "TAG")]
+    pub hashtag: Vec<String>,
+
+    /// Remove the hashtags already in the description first.
+    #[arg(long)]
+    pub replace_hashtags: bool,
+
+    /// Regular expression to replace in item names.
+    #[arg(long, value_name = "REGEX", requires = "rename_to")]
+    pub rename_from: Option<String>,
+
+    /// Replacement for --rename-from matches, "$1" inserts the first capture group.
+    #[arg(long, value_name = "TEXT", requires = "rename_from")]
+    pub rename_to: Option<String>,
+
+    /// Only show the changes, don't write them.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    #[command(flatten)]
+    pub filters: FilterArgs,
+}
+
+#[derive(Args)]
+pub struct WatchArgs {
+    #[command(flatten)]
+    pub pack: PackArgs,
+
+    /// Seconds between two looks at the Tray folder.
+    #[arg(long, value_name = "SECONDS", default_value_t = constants::WATCH_INTERVAL_SECS, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
+
+    /// Seconds the Tray folder must stay unchanged before the saved items are packed, the game writes their files one by one.
+    #[arg(long, value_name = "SECONDS", default_value_t = constants::WATCH_SETTLE_SECS)]
+    pub settle: u64,
+}
+
+#[derive(Args)]
+pub struct RestoreOutputArgs {
+    /// Output folder packed with the folder format [default: ./output].
+    pub path: Option<PathBuf>,
+
+    /// What to do with an item whose id is already used in the Tray folder [default: ask].
+    #[arg(long, value_enum, default_value_t = ConflictChoice::Ask, hide_default_value = true)]
+    pub on_conflict: ConflictChoice,
+
+    #[command(flatten)]
+    pub filters: FilterArgs,
+}
+
+#[derive(Args)]
+pub struct DuplicatesArgs {
+    /// Only list the duplicates, don't move anything.
+    #[arg(long)]
+    pub list: bool,
+
+    #[command(flatten)]
+    pub filters: FilterArgs,
+}
+
+#[derive(Args)]
+pub struct StatsArgs {
+    /// Number of items listed as the largest and the oldest.
+    #[arg(long, value_name = "COUNT", default_value_t = constants::STATS_TOP_ITEMS)]
+    pub top: usize,
+
+    #[command(flatten)]
+    pub filters: FilterArgs,
+}
+
+#[derive(Args)]
+pub struct InspectArgs {
+    /// Hexadecimal id of the item in the Tray folder, or the path of any of its files.
+    #[arg(value_name = "ID|PATH")]
+    pub item: String,
+}
+
+#[derive(Args)]
+pub struct DumpArgs {
+    /// File to dump, a trayitem or any binary of the Tray folder.
+    pub path: PathBuf,
+
+    /// Offset of the message in the file, to dump an embedded message [default: after the header, if there is one].
+    #[arg(long, value_name = "BYTES", value_parser = inspect::parse_offset)]
+    pub offset: Option<usize>,
+
+    /// Length of the message [default: up to the end of the file].
+    #[arg(long, value_name = "BYTES", value_parser = inspect::parse_offset)]
+    pub length: Option<usize>,
+
+    /// Deepest level of embedded messages decoded, deeper ones are shown as bytes.
+    #[arg(long, value_name = "LEVELS", default_value_t = constants::PROTOBUF_MAX_DEPTH)]
+    pub depth: usize,
+}
+
+#[derive(Args)]
+pub struct UndoArgs {
+    /// Only list the operations that can be undone, newest first.
+    #[arg(long)]
+    pub list: bool,
+}
+
+#[derive(Args)]
+pub struct KeygenArgs {
+    /// Replace the existing key, bundles signed with it won't be trusted by your recipients anymore.
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Clone, Copy, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemType {
+    Household,
+    Lot,
+    Room,
+}
+
+impl ItemType {
+    pub fn file_type(&self) -> u32 {
+        match self {
+            ItemType::Household => {
+                return constants::HOUSEHOLD_TRAYITEM_TYPE;
+            },
+            ItemType::Lot => {
+                return constants::PLOT_TRAYITEM_TYPE;
+            },
+            ItemType::Room => {
+                return constants::ROOM_TRAYITEM_TYPE;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    S4pack,
+    Folder,
+    Zip,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConflictChoice {
+    /// Ask for every item.
+    Ask,
+    /// Keep the item of the Tray folder.
+    Skip,
+    /// Replace the item of the Tray folder.
+    Overwrite,
+    /// Install the item as a copy with a new id.
+    Remap,
+}
@@ -0,0 +1,227 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+use serde::Deserialize;
+
+use crate::cli::{Cli, Command, FilterArgs, ItemType, OutputFormat, PackArgs, StoreArgs};
+use crate::{constants, filters, signing};
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub tray: Option<PathBuf>,
+    pub mods: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub name_template: Option<String>,
+    pub format: Option<OutputFormat>,
+    pub include_cc: Option<bool>,
+    pub anonymize: Option<bool>,
+    pub verbose: Option<bool>,
+    pub log_file: Option<PathBuf>,
+    pub pause: Option<bool>,
+    pub filters: FilterConfig,
+    pub backup: BackupConfig,
+    pub signing: SigningConfig,
+    pub anonymous: AnonymousConfig,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    #[serde(rename = "type")]
+    pub item_type: Vec<ItemType>,
+    pub name: Option<String>,
+    pub creator: Vec<String>,
+    pub id: Vec<String>,
+    pub modified_since: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    pub store: Option<PathBuf>,
+    pub keep: Option<usize>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnonymousConfig {
+    pub creator_name: Option<String>,
+    pub creator_id: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningConfig {
+    pub sign: Option<bool>,
+    pub trusted_keys: Vec<String>,
+    pub require_signature: Option<bool>,
+}
+
+pub fn get_config_path() -> Option<PathBuf> {
+    let project_dirs = ProjectDirs::from("", "", constants::APPLICATION_NAME)?;
+    return Some(project_dirs.config_dir().join(constants::CONFIG_FILENAME));
+}
+
+/// A missing default configuration file is not an error, every option just keeps its built-in default.
+pub fn load(config_override: Option<&Path>) -> Result<Config, String> {
+    let config_path = match config_override {
+        Some(config_path) => config_path.to_path_buf(),
+        None => match get_config_path() {
+            Some(config_path) => config_path,
+            None => {
+                return Ok(Config::default());
+            }
+        }
+    };
+    match fs::read_to_string(&config_path) {
+        Ok(content) => {
+            match toml::from_str(&content) {
+                Ok(config) => {
+                    return Ok(config);
+                },
+                Err(e) => {
+                    return Err(format!("Invalid configuration file {}: {}", config_path.display(), e.message()));
+                }
+            }
+        },
+        Err(e) => {
+            if e.kind() == ErrorKind::NotFound && config_override.is_none() {
+                return Ok(Config::default());
+            }
+            return Err(format!("Couldn't read configuration file {}", config_path.display()));
+        }
+    }
+}
+
+/// Fills every option that wasn't given on the command line with the configured value. Switches turned on in the
+/// configuration file are turned off again by their --no-* counterpart. The configured filters only choose what
+/// gets packed, the commands that edit, move or report on Tray files only use the filters given to them.
+pub fn apply(config: Config, args: &mut Cli) -> Result<(), String> {
+    if args.tray.is_none() {
+        args.tray = config.tray.clone();
+    }
+    if args.mods.is_none() {
+        args.mods = config.mods.clone();
+    }
+    if config.pause == Some(false) && !args.pause {
+        args.no_pause = true;
+    }
+    if config.verbose == Some(true) && args.verbose == 0 && args.quiet == 0 {
+        args.verbose = 1;
+    }
+    if args.log_file.is_none() {
+        args.log_file = config.log_file.clone();
+    }
+    match &mut args.command {
+        None => {
+            apply_pack(config, &mut args.pack)?;
+        },
+        Some(Command::Pack(pack_args)) => {
+            apply_pack(config, pack_args)?;
+        },
+        Some(Command::Backup(backup_args)) => {
+            apply_store(&config, &mut backup_args.store);
+            if backup_args.keep.is_none() {
+                backup_args.keep = config.backup.keep;
+            }
+        },
+        Some(Command::Snapshots(store_args)) => {
+            apply_store(&config, store_args);
+        },
+        Some(Command::Restore(restore_args)) => {
+            apply_store(&config, &mut restore_args.store);
+        },
+        Some(Command::Prune(prune_args)) => {
+            apply_store(&config, &mut prune_args.store);
+            if prune_args.keep.is_none() {
+                prune_args.keep = config.backup.keep;
+            }
+        },
+        Some(Command::Diff(diff_args)) => {
+            apply_store(&config, &mut diff_args.store);
+        },
+        Some(Command::Unpack(unpack_args)) => {
+            if unpack_args.trusted_key.is_empty() {
+                for trusted_key in &config.signing.trusted_keys {
+                    unpack_args.trusted_key.push(signing::parse_public_key(trusted_key)?);
+                }
+            }
+            if config.signing.require_signature == Some(true) && !unpack_args.no_require_signature {
+                unpack_args.require_signature = true;
+            }
+        },
+        Some(Command::Keygen(_)) | Some(Command::Undo(_)) | Some(Command::Inspect(_)) | Some(Command::Dump(_)) => {},
+        Some(Command::Deps(_)) | Some(Command::Edit(_)) | Some(Command::Duplicates(_)) | Some(Command::Stats(_)) => {},
+        Some(Command::Watch(watch_args)) => {
+            apply_pack(config, &mut watch_args.pack)?;
+        },
+        Some(Command::RestoreOutput(restore_output_args)) => {
+            if restore_output_args.path.is_none() {
+                restore_output_args.path = config.output;
+            }
+        },
+    }
+    return Ok(());
+}
+
+fn apply_store(config: &Config, store_args: &mut StoreArgs) {
+    if store_args.store.is_none() {
+        store_args.store = config.backup.store.clone();
+    }
+}
+
+fn apply_pack(config: Config, pack_args: &mut PackArgs) -> Result<(), String> {
+    if pack_args.output.is_none() {
+        pack_args.output = config.output;
+    }
+    if pack_args.name_template.is_none() {
+        pack_args.name_template = config.name_template;
+    }
+    if pack_args.format.is_none() {
+        pack_args.format = config.format;
+    }
+    if config.include_cc == Some(true) && !pack_args.no_include_cc {
+        pack_args.include_cc = true;
+    }
+    if config.anonymize == Some(true) && !pack_args.no_anonymize {
+        pack_args.anonymize = true;
+    }
+    if pack_args.anonymous_name.is_none() {
+        pack_args.anonymous_name = config.anonymous.creator_name;
+    }
+    if pack_args.anonymous_id.is_none() {
+        if let Some(creator_id) = &config.anonymous.creator_id {
+            pack_args.anonymous_id = Some(filters::parse_id(creator_id)?);
+        }
+    }
+    if config.signing.sign == Some(true) && !pack_args.no_sign {
+        pack_args.sign = true;
+    }
+    return apply_filters(config.filters, &mut pack_args.filters);
+}
+
+fn apply_filters(config: FilterConfig, filter_args: &mut FilterArgs) -> Result<(), String> {
+    if filter_args.item_type.is_empty() {
+        filter_args.item_type = config.item_type;
+    }
+    if filter_args.name.is_none() {
+        filter_args.name = config.name;
+    }
+    if filter_args.creator.is_empty() {
+        filter_args.creator = config.creator;
+    }
+    if filter_args.id.is_empty() {
+        for id in &config.id {
+            filter_args.id.push(filters::parse_id(id)?);
+        }
+    }
+    if filter_args.modified_since.is_none() {
+        if let Some(modified_since) = &config.modified_since {
+            filter_args.modified_since = Some(filters::parse_date(modified_since)?);
+        }
+    }
+    return Ok(());
+}
@@ -6,14 +6,89 @@ pub const BPI_EXT: &str = "bpi";
 pub const ROOM_EXT: &str = "room";
 pub const RMI_EXT: &str = "rmi";
 pub const SGI_EXT: &str = "sgi";
+pub const PACKAGE_EXT: &str = "package";
+pub const TRAY_FILE_EXTENSIONS: [&str; 8] = [TRAYITEM_EXT, HOUSEHOLDBINARY_EXT, HHI_EXT, BLUEPRINT_EXT, BPI_EXT, ROOM_EXT, RMI_EXT, SGI_EXT];
 
 pub const HOUSEHOLD_TRAYITEM_TYPE: u32 = 0x01;
 pub const PLOT_TRAYITEM_TYPE: u32 = 0x02;
 pub const ROOM_TRAYITEM_TYPE: u32 = 0x03;
 
-pub const HOUSEHOLD_TRAYITEM_NAME_OFFSET: usize = 0x26;
-pub const ROOM_PLOT_TRAYITEM_NAME_OFFSET: usize = 0x27;
+pub const TRAYITEM_HEADER_SIZE: usize = 0x08;
+pub const TRAYITEM_ID_FIELD: u32 = 1;
+pub const TRAYITEM_TYPE_FIELD: u32 = 2;
+pub const TRAYITEM_REMOTE_ID_FIELD: u32 = 3;
+pub const TRAYITEM_NAME_FIELD: u32 = 4;
+pub const TRAYITEM_DESCRIPTION_FIELD: u32 = 5;
+pub const TRAYITEM_CREATOR_ID_FIELD: u32 = 6;
+pub const TRAYITEM_CREATOR_NAME_FIELD: u32 = 7;
+pub const CREATOR_ID_FIELDS: [u32; 1] = [TRAYITEM_CREATOR_ID_FIELD];
+pub const CREATOR_REFERENCING_EXTENSIONS: [&str; 3] = [HOUSEHOLDBINARY_EXT, BLUEPRINT_EXT, ROOM_EXT];
+
+pub const EA_FOLDER_NAME: &str = "Electronic Arts";
+pub const SIMS_FOLDER_NAMES: [&str; 5] = ["The Sims 4", "Die Sims 4", "Les Sims 4", "Los Sims 4", "De Sims 4"];
+pub const TRAY_FOLDER_NAME: &str = "Tray";
+pub const MODS_FOLDER_NAME: &str = "Mods";
+pub const WINE_DOCUMENTS_FOLDER_NAMES: [&str; 2] = ["Documents", "My Documents"];
+
+pub const PROTOBUF_MAX_DEPTH: usize = 16;
+
+pub const DBPF_MAGIC: &[u8; 4] = b"DBPF";
+pub const DBPF_HEADER_SIZE: usize = 0x60;
+pub const DBPF_INDEX_ENTRY_MIN_SIZE: usize = 16;
+pub const DBPF_RESOURCE_MAX_SIZE: u32 = 256 * 1024 * 1024;
+pub const DBPF_COMPRESSION_NONE: u16 = 0x0000;
+pub const DBPF_COMPRESSION_ZLIB: u16 = 0x5A42;
+pub const DBPF_COMPRESSION_INTERNAL: u16 = 0xFFFF;
+pub const DBPF_COMPRESSION_DELETED: u16 = 0xFFE0;
+pub const OBJECT_DEFINITION_RESOURCE_TYPE: u32 = 0xC0DB5AE7;
+pub const OBJECT_CATALOG_RESOURCE_TYPE: u32 = 0x319E4F1D;
+pub const CAS_PART_RESOURCE_TYPE: u32 = 0x034AEECB;
+pub const CUSTOM_CONTENT_RESOURCE_TYPES: [u32; 3] = [OBJECT_DEFINITION_RESOURCE_TYPE, OBJECT_CATALOG_RESOURCE_TYPE, CAS_PART_RESOURCE_TYPE];
+pub const CUSTOM_CONTENT_REFERENCING_EXTENSIONS: [&str; 3] = [HOUSEHOLDBINARY_EXT, BLUEPRINT_EXT, ROOM_EXT];
 
 pub const HOUSEHOLD_FOLDERNAME: &str = "households";
 pub const PLOT_FOLDERNAME: &str = "plots";
-pub const ROOM_FOLDERNAME: &str = "rooms";
\ No newline at end of file
+pub const ROOM_FOLDERNAME: &str = "rooms";
+
+// Exit code 2 is what clap uses for invalid arguments.
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_PARTIAL_FAILURE: i32 = 3;
+pub const EXIT_NOTHING_TO_DO: i32 = 4;
+pub const EXIT_CODES_HELP: &str = "Exit codes: 0 success, 1 failure, 2 invalid arguments, 3 some items failed, 4 nothing to do.";
+
+pub const APPLICATION_NAME: &str = "sims-4-gallery-packer";
+pub const CONFIG_FILENAME: &str = "config.toml";
+pub const BACKUP_STORE_FOLDERNAME: &str = "backups";
+pub const BACKUP_OBJECTS_FOLDERNAME: &str = "objects";
+pub const BACKUP_SNAPSHOTS_FOLDERNAME: &str = "snapshots";
+pub const JOURNAL_FOLDERNAME: &str = "journal";
+pub const JOURNAL_FILENAME: &str = "journal.toml";
+pub const JOURNAL_KEEP: usize = 20;
+pub const QUARANTINE_FOLDERNAME: &str = "quarantine";
+
+pub const DEFAULT_NAME_TEMPLATE: &str = "{type}/{name} ({id})";
+
+pub const BUNDLE_EXT: &str = "s4pack";
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+pub const BUNDLE_MANIFEST_FILENAME: &str = "manifest.toml";
+pub const BUNDLE_SIGNATURE_FILENAME: &str = "manifest.sig";
+pub const BUNDLE_MANIFEST_MAX_SIZE: u64 = 16 * 1024 * 1024;
+pub const BUNDLE_SIGNATURE_MAX_SIZE: u64 = 64 * 1024;
+pub const BUNDLE_DEFAULT_NAME: &str = "gallery";
+pub const SIGNING_KEY_FILENAME: &str = "signing.key";
+
+pub const ANONYMOUS_CREATOR_NAME: &str = "Anonymous";
+pub const ANONYMOUS_CREATOR_ID: u64 = 0;
+
+pub const STAGING_FOLDERNAME: &str = ".staging";
+pub const OUTPUT_MARKER_FILENAME: &str = ".sims-4-gallery-packer";
+
+pub const WATCH_INTERVAL_SECS: u64 = 2;
+pub const WATCH_SETTLE_SECS: u64 = 5;
+
+pub const PROGRESS_TEMPLATE: &str = "[{bar:30.cyan/blue}] {pos}/{len} items, {msg}, ETA {eta}";
+pub const PROGRESS_CHARS: &str = "=> ";
+pub const SUMMARY_MAX_SKIPPED_ITEMS: usize = 10;
+pub const STATS_TOP_ITEMS: usize = 5;
+pub const DUMP_MAX_DEPTH: usize = 8;
\ No newline at end of file
@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::dbpf::Package;
+use crate::protobuf::{self, WireValue};
+use crate::tray_files::{TrayItem, TrayItemFile};
+use crate::filters::TrayItemFilter;
+use crate::utils::Outcome;
+use crate::{constants, packing, tray_metadata, utils};
+
+/// Which packages of the Mods folder define each object, catalog entry and CAS part instance.
+pub struct ModsIndex {
+    pub mods_folder: PathBuf,
+    pub packages: Vec<PathBuf>,
+    instances: HashMap<u64, Vec<usize>>,
+}
+
+impl ModsIndex {
+    pub fn new(mods_folder: &Path) -> ModsIndex {
+        let package_paths: Vec<PathBuf> = utils::list_files_recursive(mods_folder).into_iter()
+            .filter(|path| path.extension().and_then(|extension| extension.to_str()) == Some(constants::PACKAGE_EXT))
+            .collect();
+        let package_instances = utils::parallel_map(&package_paths, |path| read_package_instances(path));
+        let mut packages: Vec<PathBuf> = Vec::new();
+        let mut instances: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (path, package_instances) in package_paths.into_iter().zip(package_instances) {
+            match package_instances {
+                Ok(package_instances) => {
+                    let package_index = packages.len();
+                    for instance in package_instances {
+                        instances.entry(instance).or_default().push(package_index);
+                    }
+                    packages.push(path);
+                },
+                Err(e) => {
+                    utils::print_warning(format!("{}, skipping! ({})", e, path.display()));
+                }
+            }
+        }
+        utils::print_info(format!("Indexed {} packages in {}.", packages.len(), mods_folder.display()));
+        return ModsIndex {
+            mods_folder: mods_folder.to_path_buf(),
+            packages,
+            instances,
+        };
+    }
+
+    /// Packages defining an instance id referenced by the item binaries, sorted by path.
+    pub fn find_dependencies(&self, tray_item: &TrayItem, companion_files: &[&TrayItemFile]) -> Vec<&PathBuf> {
+        let mut referenced_ids: HashSet<u64> = HashSet::new();
+        let paths = std::iter::once(&tray_item.path).chain(companion_files.iter().map(|companion_file| &companion_file.path));
+        for path in paths {
+            let is_binary = path.extension().and_then(|extension| extension.to_str())
+                .map(|extension| constants::CUSTOM_CONTENT_REFERENCING_EXTENSIONS.contains(&extension))
+                .unwrap_or(false);
+            if is_binary {
+                if let Ok(content) = fs::read(path) {
+                    if !collect_referenced_ids(&content, &mut referenced_ids) {
+                        utils::print_warning(format!("[{}] {} has an unknown header, the custom content it uses can't be detected.", tray_item.name, path.display()));
+                    }
+                }
+            }
+        }
+        let referenced_ids_count = referenced_ids.len();
+        let mut package_indexes: Vec<usize> = Vec::new();
+        for id in referenced_ids {
+            if let Some(indexes) = self.instances.get(&id) {
+                package_indexes.extend(indexes);
+            }
+        }
+        package_indexes.sort();
+        package_indexes.dedup();
+        utils::print_debug(format!("[{}] References {} possible resource ids, {} of them found in the Mods folder.", tray_item.name, referenced_ids_count, package_indexes.len()));
+        return package_indexes.into_iter().map(|package_index| &self.packages[package_index]).collect();
+    }
+}
+
+pub fn list_dependencies(tray_folder: &Path, mods_override: Option<&Path>, filter: &TrayItemFilter) -> Outcome {
+    if let Some(mods_folder) = get_mods_folder(tray_folder, mods_override) {
+        let mods_index = ModsIndex::new(&mods_folder);
+        let tray_content = packing::read_selected_tray_files(tray_folder, filter, false);
+        for tray_item in &tray_content.trayitem_files {
+            let companion_files = tray_content.companion_files(tray_item);
+            let packages = mods_index.find_dependencies(tray_item, &companion_files);
+            if packages.is_empty() {
+                utils::print_success(format!("[{}] No custom content needed.", tray_item.name));
+            } else {
+                utils::print_warning(format!("[{}] Needs {} custom content packages:", tray_item.name, packages.len()));
+                for package in packages {
+                    utils::print_info(format!("    {}", package.strip_prefix(&mods_folder).unwrap_or(package).display()));
+                }
+            }
+        }
+        if tray_content.trayitem_files.is_empty() {
+            utils::print_info(String::from("There are no gallery items to check."));
+            return Outcome::NothingToDo;
+        }
+        return Outcome::Success;
+    }
+    return Outcome::Failure;
+}
+
+pub fn get_mods_folder(tray_folder: &Path, mods_override: Option<&Path>) -> Option<PathBuf> {
+    let mods_folder = match mods_override {
+        Some(mods_folder) => mods_folder.to_path_buf(),
+        None => match tray_folder.parent() {
+            Some(game_folder) => game_folder.join(constants::MODS_FOLDER_NAME),
+            None => {
+                utils::print_error(String::from("Couldn't find the Mods folder, use --mods to set its location!"));
+                return None;
+            }
+        }
+    };
+    if !mods_folder.is_dir() {
+        utils::print_error(format!("Mods folder {} doesn't exists!", mods_folder.display()));
+        return None;
+    }
+    utils::print_debug(format!("Using Mods folder {}", mods_folder.display()));
+    return Some(mods_folder);
+}
+
+/// Custom content instance ids defined by the package, each listed once.
+fn read_package_instances(path: &Path) -> Result<Vec<u64>, String> {
+    let package = Package::open(path)?;
+    let mut instances: Vec<u64> = package.entries.iter()
+        .filter(|entry| constants::CUSTOM_CONTENT_RESOURCE_TYPES.contains(&entry.key.type_id))
+        .map(|entry| entry.key.instance)
+        .collect();
+    instances.sort();
+    instances.dedup();
+    utils::print_trace(format!("{} holds {} resources, {} of them custom content.", path.display(), package.entries.len(), instances.len()));
+    return Ok(instances);
+}
+
+/// The binaries are protobuf messages behind the same header as the trayitem, so every 64 bit number found while walking
+/// the message tree is a candidate resource instance. Instances are hashes, so false matches are very unlikely.
+/// A file with another header is left out rather than parsed from a guessed offset.
+fn collect_referenced_ids(content: &[u8], referenced_ids: &mut HashSet<u64>) -> bool {
+    match tray_metadata::get_message(content).and_then(protobuf::read_fields) {
+        Some(fields) => {
+            collect_message_ids(&fields, referenced_ids, 0);
+            return true;
+        },
+        None => {
+            return false;
+        }
+    }
+}
+
+fn collect_message_ids(fields: &[protobuf::Field], referenced_ids: &mut HashSet<u64>, depth: usize) {
+    for field in fields {
+        match field.value {
+            WireValue::Varint(value) | WireValue::Fixed64(value) => {
+                if value > u32::MAX as u64 {
+                    referenced_ids.insert(value);
+                }
+            },
+            WireValue::LengthDelimited(bytes) => {
+                if depth < constants::PROTOBUF_MAX_DEPTH {
+                    if let Some(nested_fields) = protobuf::read_fields(bytes) {
+                        collect_message_ids(&nested_fields, referenced_ids, depth + 1);
+                    }
+                }
+                // Packed repeated ids are stored as plain little endian numbers.
+                if bytes.len() % 8 == 0 {
+                    for chunk in bytes.chunks_exact(8) {
+                        let value = u64::from_le_bytes(chunk.try_into().unwrap_or([0; 8]));
+                        if value > u32::MAX as u64 {
+                            referenced_ids.insert(value);
+                        }
+                    }
+                }
+            },
+            WireValue::Fixed32(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protobuf::Field;
+
+    /// A room binary laid out like the game writes it: the room id, one message per placed object holding its
+    /// definition instance and catalog instance next to small values, and the packed fixed64 list of CAS parts.
+    fn build_room(object_instances: &[(u64, u64)], cas_part_instances: &[u64]) -> Vec<u8> {
+        let objects: Vec<Vec<u8>> = object_instances.iter().map(|(definition_instance, catalog_instance)| protobuf::write_fields(&[
+            Field { number: 1, value: WireValue::Fixed64(*definition_instance) },
+            Field { number: 2, value: WireValue::Varint(*catalog_instance) },
+            Field { number: 3, value: WireValue::Fixed32(0x4120_0000) },
+            Field { number: 4, value: WireValue::Varint(2) },
+        ])).collect();
+        let cas_parts: Vec<u8> = cas_part_instances.iter().flat_map(|instance| instance.to_le_bytes()).collect();
+        let mut fields = vec![Field { number: 1, value: WireValue::Varint(0x2a) }];
+        fields.extend(objects.iter().map(|object| Field { number: 2, value: WireValue::LengthDelimited(object) }));
+        fields.push(Field { number: 3, value: WireValue::LengthDelimited(&cas_parts) });
+        fields.push(Field { number: 4, value: WireValue::Varint(u32::MAX as u64) });
+        return tray_metadata::with_header(&[0u8; 4], &protobuf::write_fields(&fields));
+    }
+
+    #[test]
+    fn collect_referenced_ids_keeps_every_64_bit_value_of_a_room() {
+        let content = build_room(&[(0x9f1c_27e3_0a5d_4b61, 0x0000_0001_0000_0000), (0x3d2e_8c41_77f0_12ab, 0x5e6f_7081_92a3_b4c5)], &[0x8a9b_acbd_cedf_e0f1]);
+        let mut referenced_ids: HashSet<u64> = HashSet::new();
+        assert!(collect_referenced_ids(&content, &mut referenced_ids));
+        let mut referenced_ids: Vec<u64> = referenced_ids.into_iter().collect();
+        referenced_ids.sort();
+        assert_eq!(referenced_ids, vec![0x0000_0001_0000_0000, 0x3d2e_8c41_77f0_12ab, 0x5e6f_7081_92a3_b4c5, 0x8a9b_acbd_cedf_e0f1, 0x9f1c_27e3_0a5d_4b61]);
+    }
+
+    #[test]
+    fn collect_referenced_ids_refuses_an_unknown_header() {
+        let content = build_room(&[(0x9f1c_27e3_0a5d_4b61, 0x5e6f_7081_92a3_b4c5)], &[]);
+        let mut referenced_ids: HashSet<u64> = HashSet::new();
+        assert!(!collect_referenced_ids(&content[constants::TRAYITEM_HEADER_SIZE..], &mut referenced_ids));
+        let mut wrong_length = content.clone();
+        wrong_length[4] += 1;
+        assert!(!collect_referenced_ids(&wrong_length, &mut referenced_ids));
+        let mut not_empty = content.clone();
+        not_empty[0] = 1;
+        assert!(!collect_referenced_ids(&not_empty, &mut referenced_ids));
+        assert!(!collect_referenced_ids(&content[..4], &mut referenced_ids));
+        assert!(referenced_ids.is_empty());
+    }
+}
@@ -0,0 +1,436 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use flate2::read::ZlibDecoder;
+
+use crate::constants;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceKey {
+    pub type_id: u32,
+    pub group: u32,
+    pub instance: u64,
+}
+
+#[derive(Clone, Copy)]
+pub struct IndexEntry {
+    pub key: ResourceKey,
+    pub position: u64,
+    pub compressed_size: u32,
+    pub size: u32,
+    pub compression: u16,
+}
+
+/// A Sims 4 `.package` file (DBPF version 2), resources are only read when asked for.
+pub struct Package<R> {
+    reader: R,
+    length: u64,
+    pub major_version: u32,
+    pub minor_version: u32,
+    pub entries: Vec<IndexEntry>,
+}
+
+impl Package<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Package<BufReader<File>>, String> {
+        match File::open(path) {
+            Ok(file) => {
+                return Package::read(BufReader::new(file));
+            },
+            Err(_) => {
+                return Err(format!("Couldn't open package {}", path.display()));
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> Package<R> {
+    /// Reads the header and the index table, any `Read + Seek` source works so packages can be read from memory.
+    /// Packages come from anywhere, every size they give is checked against the file length before being used.
+    pub fn read(mut reader: R) -> Result<Package<R>, String> {
+        let length = match reader.seek(SeekFrom::End(0)) {
+            Ok(length) => length,
+            Err(_) => {
+                return Err(String::from("Couldn't read the package length"));
+            }
+        };
+        let mut header = [0u8; constants::DBPF_HEADER_SIZE];
+        if reader.seek(SeekFrom::Start(0)).is_err() || reader.read_exact(&mut header).is_err() {
+            return Err(String::from("Package is too small to contain a DBPF header"));
+        }
+        if &header[..4] != constants::DBPF_MAGIC {
+            return Err(String::from("Missing DBPF magic, this isn't a package file"));
+        }
+        let major_version = read_u32(&header, 0x04);
+        let minor_version = read_u32(&header, 0x08);
+        if major_version != 2 {
+            return Err(format!("Unsupported DBPF version {}.{}", major_version, minor_version));
+        }
+        let entry_count = read_u32(&header, 0x24) as usize;
+        let index_size = read_u32(&header, 0x2C) as usize;
+        let mut index_position = read_u64(&header, 0x40);
+        if index_position == 0 {
+            index_position = read_u32(&header, 0x28) as u64;
+        }
+        let mut entries: Vec<IndexEntry> = Vec::new();
+        if entry_count > 0 {
+            if index_position.checked_add(index_size as u64).is_none_or(|index_end| index_end > length) {
+                return Err(String::from("Package index lies past the end of the file"));
+            }
+            // Even when the flags share the rest of the key, every entry stores its instance, position and sizes.
+            if entry_count > index_size / constants::DBPF_INDEX_ENTRY_MIN_SIZE {
+                return Err(String::from("Package index is too small for its entry count"));
+            }
+            let mut index = vec![0u8; index_size];
+            if reader.seek(SeekFrom::Start(index_position)).is_err() || reader.read_exact(&mut index).is_err() {
+                return Err(String::from("Couldn't read the package index"));
+            }
+            match parse_index(&index, entry_count) {
+                Some(parsed_entries) => {
+                    entries = parsed_entries;
+                },
+                None => {
+                    return Err(String::from("Package index is truncated or damaged"));
+                }
+            }
+        }
+        return Ok(Package {
+            reader,
+            length,
+            major_version,
+            minor_version,
+            entries,
+        });
+    }
+
+    pub fn find(&self, key: &ResourceKey) -> Option<&IndexEntry> {
+        return self.entries.iter().find(|entry| entry.key == *key);
+    }
+
+    /// Returns the resource content, decompressed. A compressed resource never grows past the size in the index,
+    /// and that size is itself capped since it comes from the package.
+    pub fn read_resource(&mut self, entry: &IndexEntry) -> Result<Vec<u8>, String> {
+        if entry.size > constants::DBPF_RESOURCE_MAX_SIZE {
+            return Err(format!("Resource {} is too large to be read", format_key(&entry.key)));
+        }
+        if entry.position.checked_add(entry.compressed_size as u64).is_none_or(|end| end > self.length) {
+            return Err(format!("Resource {} lies past the end of the package", format_key(&entry.key)));
+        }
+        let mut stored = vec![0u8; entry.compressed_size as usize];
+        if self.reader.seek(SeekFrom::Start(entry.position)).is_err() || self.reader.read_exact(&mut stored).is_err() {
+            return Err(format!("Couldn't read resource {}", format_key(&entry.key)));
+        }
+        match entry.compression {
+            constants::DBPF_COMPRESSION_NONE => {
+                return Ok(stored);
+            },
+            constants::DBPF_COMPRESSION_ZLIB => {
+                let mut content: Vec<u8> = Vec::new();
+                // One byte more than expected is enough to tell that the resource is larger than the index says.
+                match ZlibDecoder::new(stored.as_slice()).take(entry.size as u64 + 1).read_to_end(&mut content) {
+                    Ok(_) if content.len() == entry.size as usize => {
+                        return Ok(content);
+                    },
+                    _ => {
+                        return Err(format!("Couldn't decompress resource {}", format_key(&entry.key)));
+                    }
+                }
+            },
+            constants::DBPF_COMPRESSION_INTERNAL => {
+                return decompress_internal(&stored)
+                    .filter(|content| content.len() == entry.size as usize)
+                    .ok_or(format!("Couldn't decompress resource {}", format_key(&entry.key)));
+            },
+            constants::DBPF_COMPRESSION_DELETED => {
+                return Err(format!("Resource {} is marked as deleted", format_key(&entry.key)));
+            },
+            compression => {
+                return Err(format!("Resource {} uses unsupported compression 0x{:04X}", format_key(&entry.key), compression));
+            }
+        }
+    }
+}
+
+pub fn format_key(key: &ResourceKey) -> String {
+    return format!("{:08X}:{:08X}:{:016X}", key.type_id, key.group, key.instance);
+}
+
+/// The index starts with flags telling which key parts are shared by every entry and stored only once.
+fn parse_index(index: &[u8], entry_count: usize) -> Option<Vec<IndexEntry>> {
+    let flags = read_u32_checked(index, 0)?;
+    let mut position = 4;
+    let mut constant_type: Option<u32> = None;
+    let mut constant_group: Option<u32> = None;
+    let mut constant_instance_high: Option<u32> = None;
+    if flags & 0x01 != 0 {
+        constant_type = Some(read_u32_checked(index, position)?);
+        position += 4;
+    }
+    if flags & 0x02 != 0 {
+        constant_group = Some(read_u32_checked(index, position)?);
+        position += 4;
+    }
+    if flags & 0x04 != 0 {
+        constant_instance_high = Some(read_u32_checked(index, position)?);
+        position += 4;
+    }
+    let mut entries: Vec<IndexEntry> = Vec::with_capacity(entry_count);
+    for _ in 0..entry_count {
+        let mut next_u32 = || {
+            let value = read_u32_checked(index, position);
+            position += 4;
+            return value;
+        };
+        let type_id = match constant_type {
+            Some(type_id) => type_id,
+            None => next_u32()?
+        };
+        let group = match constant_group {
+            Some(group) => group,
+            None => next_u32()?
+        };
+        let instance_high = match constant_instance_high {
+            Some(instance_high) => instance_high,
+            None => next_u32()?
+        };
+        let instance_low = next_u32()?;
+        let entry_position = next_u32()?;
+        let compressed_size = next_u32()?;
+        let size = next_u32()?;
+        let mut compression = constants::DBPF_COMPRESSION_NONE;
+        // The high bit of the stored size tells whether the compression type and committed flag follow.
+        if compressed_size & 0x80000000 != 0 {
+            compression = u16::from_le_bytes(index.get(position..position + 2)?.try_into().ok()?);
+            index.get(position..position + 4)?;
+            position += 4;
+        }
+        entries.push(IndexEntry {
+            key: ResourceKey {
+                type_id,
+                group,
+                instance: ((instance_high as u64) << 32) | instance_low as u64,
+            },
+            position: entry_position as u64,
+            compressed_size: compressed_size & 0x7FFFFFFF,
+            size,
+            compression,
+        });
+    }
+    return Some(entries);
+}
+
+/// Decompresses the RefPack based "internal" compression: a short header with the decompressed size, then
+/// commands copying literal bytes from the input and back references from the already decompressed output.
+pub fn decompress_internal(data: &[u8]) -> Option<Vec<u8>> {
+    let flags = *data.first()?;
+    if *data.get(1)? != 0xFB {
+        return None;
+    }
+    let size_length = if flags & 0x80 != 0 { 4 } else { 3 };
+    let mut position = 2;
+    if flags & 0x01 != 0 {
+        position += size_length;
+    }
+    let mut size: usize = 0;
+    for byte in data.get(position..position + size_length)? {
+        size = (size << 8) | *byte as usize;
+    }
+    position += size_length;
+    if size > constants::DBPF_RESOURCE_MAX_SIZE as usize {
+        return None;
+    }
+    // The size comes from the data, it is only trusted as a limit.
+    let mut output: Vec<u8> = Vec::with_capacity(size.min(data.len()));
+    loop {
+        let control = *data.get(position)? as usize;
+        let literal_length: usize;
+        let copy_length: usize;
+        let copy_offset: usize;
+        if control < 0x80 {
+            let byte_1 = *data.get(position + 1)? as usize;
+            literal_length = control & 0x03;
+            copy_length = ((control & 0x1C) >> 2) + 3;
+            copy_offset = ((control & 0x60) << 3) + byte_1 + 1;
+            position += 2;
+        } else if control < 0xC0 {
+            let byte_1 = *data.get(position + 1)? as usize;
+            let byte_2 = *data.get(position + 2)? as usize;
+            literal_length = (byte_1 & 0xC0) >> 6;
+            copy_length = (control & 0x3F) + 4;
+            copy_offset = ((byte_1 & 0x3F) << 8) + byte_2 + 1;
+            position += 3;
+        } else if control < 0xE0 {
+            let byte_1 = *data.get(position + 1)? as usize;
+            let byte_2 = *data.get(position + 2)? as usize;
+            let byte_3 = *data.get(position + 3)? as usize;
+            literal_length = control & 0x03;
+            copy_length = ((control & 0x0C) << 6) + byte_3 + 5;
+            copy_offset = ((control & 0x10) << 12) + (byte_1 << 8) + byte_2 + 1;
+            position += 4;
+        } else if control < 0xFC {
+            literal_length = ((control & 0x1F) << 2) + 4;
+            copy_length = 0;
+            copy_offset = 0;
+            position += 1;
+        } else {
+            literal_length = control & 0x03;
+            output.extend_from_slice(data.get(position + 1..position + 1 + literal_length)?);
+            break;
+        }
+        if output.len() + literal_length + copy_length > size {
+            return None;
+        }
+        output.extend_from_slice(data.get(position..position + literal_length)?);
+        position += literal_length;
+        if copy_length > 0 {
+            let start = output.len().checked_sub(copy_offset)?;
+            // Back references may overlap the bytes they produce, so they have to be copied one at a time.
+            for index in 0..copy_length {
+                let byte = output[start + index];
+                output.push(byte);
+            }
+        }
+    }
+    if output.len() != size {
+        return None;
+    }
+    return Some(output);
+}
+
+fn read_u32(content: &[u8], offset: usize) -> u32 {
+    return u32::from_le_bytes([content[offset], content[offset + 1], content[offset + 2], content[offset + 3]]);
+}
+
+fn read_u64(content: &[u8], offset: usize) -> u64 {
+    return read_u32(content, offset) as u64 | ((read_u32(content, offset + 4) as u64) << 32);
+}
+
+fn read_u32_checked(content: &[u8], offset: usize) -> Option<u32> {
+    return Some(u32::from_le_bytes(content.get(offset..offset + 4)?.try_into().ok()?));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    const TYPE_ID: u32 = constants::OBJECT_DEFINITION_RESOURCE_TYPE;
+    const GROUP: u32 = 0x80000000;
+
+    /// Stored bytes, decompressed size and compression of one resource.
+    struct TestResource {
+        instance: u64,
+        stored: Vec<u8>,
+        size: u32,
+        compression: u16,
+    }
+
+    /// A version 2.1 package with every resource after the header and the index at the end. The type and group are
+    /// shared by every entry, so they are stored once after the index flags.
+    fn build_package(resources: &[TestResource]) -> Vec<u8> {
+        let mut content = vec![0u8; constants::DBPF_HEADER_SIZE];
+        let mut index: Vec<u8> = Vec::new();
+        index.extend_from_slice(&0x03u32.to_le_bytes());
+        index.extend_from_slice(&TYPE_ID.to_le_bytes());
+        index.extend_from_slice(&GROUP.to_le_bytes());
+        for resource in resources {
+            index.extend_from_slice(&((resource.instance >> 32) as u32).to_le_bytes());
+            index.extend_from_slice(&(resource.instance as u32).to_le_bytes());
+            index.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            index.extend_from_slice(&(resource.stored.len() as u32 | 0x80000000).to_le_bytes());
+            index.extend_from_slice(&resource.size.to_le_bytes());
+            index.extend_from_slice(&resource.compression.to_le_bytes());
+            index.extend_from_slice(&1u16.to_le_bytes());
+            content.extend_from_slice(&resource.stored);
+        }
+        let index_position = content.len() as u64;
+        content.extend_from_slice(&index);
+        content[..4].copy_from_slice(constants::DBPF_MAGIC);
+        content[0x04..0x08].copy_from_slice(&2u32.to_le_bytes());
+        content[0x08..0x0C].copy_from_slice(&1u32.to_le_bytes());
+        content[0x24..0x28].copy_from_slice(&(resources.len() as u32).to_le_bytes());
+        content[0x2C..0x30].copy_from_slice(&(index.len() as u32).to_le_bytes());
+        content[0x40..0x48].copy_from_slice(&index_position.to_le_bytes());
+        return content;
+    }
+
+    fn uncompressed(instance: u64, content: &[u8]) -> TestResource {
+        return TestResource { instance, stored: content.to_vec(), size: content.len() as u32, compression: constants::DBPF_COMPRESSION_NONE };
+    }
+
+    fn read_first_resource(content: Vec<u8>) -> Result<Vec<u8>, String> {
+        let mut package = Package::read(Cursor::new(content))?;
+        let entry = package.entries[0];
+        return package.read_resource(&entry);
+    }
+
+    #[test]
+    fn read_parses_the_header_and_index() {
+        let package = Package::read(Cursor::new(build_package(&[uncompressed(0x0011223344556677, b"first"), uncompressed(0x8899AABBCCDDEEFF, b"second")]))).unwrap();
+        assert_eq!((package.major_version, package.minor_version), (2, 1));
+        assert_eq!(package.entries.len(), 2);
+        let keys: Vec<ResourceKey> = package.entries.iter().map(|entry| entry.key).collect();
+        assert!(keys == vec![
+            ResourceKey { type_id: TYPE_ID, group: GROUP, instance: 0x0011223344556677 },
+            ResourceKey { type_id: TYPE_ID, group: GROUP, instance: 0x8899AABBCCDDEEFF },
+        ]);
+        assert_eq!(package.entries[1].position, constants::DBPF_HEADER_SIZE as u64 + 5);
+        assert!(package.find(&keys[1]).is_some());
+    }
+
+    #[test]
+    fn read_resource_returns_uncompressed_content() {
+        assert_eq!(read_first_resource(build_package(&[uncompressed(1, b"plain resource")])).unwrap(), b"plain resource");
+    }
+
+    #[test]
+    fn read_resource_inflates_zlib_content() {
+        let content = b"zlib resource, zlib resource, zlib resource".to_vec();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&content).unwrap();
+        let stored = encoder.finish().unwrap();
+        let resource = TestResource { instance: 1, stored: stored.clone(), size: content.len() as u32, compression: constants::DBPF_COMPRESSION_ZLIB };
+        assert_eq!(read_first_resource(build_package(&[resource])).unwrap(), content);
+        // A resource inflating past the size given in the index is refused.
+        let resource = TestResource { instance: 1, stored, size: 10, compression: constants::DBPF_COMPRESSION_ZLIB };
+        assert!(read_first_resource(build_package(&[resource])).is_err());
+    }
+
+    #[test]
+    fn read_resource_refuses_sizes_over_the_cap() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"zlib resource").unwrap();
+        let resource = TestResource { instance: 1, stored: encoder.finish().unwrap(), size: constants::DBPF_RESOURCE_MAX_SIZE + 1, compression: constants::DBPF_COMPRESSION_ZLIB };
+        assert_eq!(read_first_resource(build_package(&[resource])).unwrap_err(), "Resource C0DB5AE7:80000000:0000000000000001 is too large to be read");
+    }
+
+    #[test]
+    fn read_resource_decompresses_internal_content() {
+        // Four literal bytes, then a back reference copying 8 bytes from 3 bytes back, then the end marker.
+        let stored = vec![0x10, 0xFB, 0x00, 0x00, 0x0C, 0xE0, b'a', b'b', b'c', b'a', 0x14, 0x02, 0xFC];
+        let resource = TestResource { instance: 1, stored, size: 12, compression: constants::DBPF_COMPRESSION_INTERNAL };
+        assert_eq!(read_first_resource(build_package(&[resource])).unwrap(), b"abcabcabcabc");
+    }
+
+    #[test]
+    fn read_refuses_truncated_or_damaged_packages() {
+        let content = build_package(&[uncompressed(1, b"plain resource")]);
+        assert!(Package::read(Cursor::new(content[..0x20].to_vec())).is_err());
+        let mut wrong_magic = content.clone();
+        wrong_magic[0] = b'X';
+        assert!(Package::read(Cursor::new(wrong_magic)).is_err());
+        assert!(Package::read(Cursor::new(content[..content.len() - 1].to_vec())).is_err());
+        let mut huge_entry_count = content.clone();
+        huge_entry_count[0x24..0x28].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Package::read(Cursor::new(huge_entry_count)).is_err());
+        let mut huge_resource = content.clone();
+        let compressed_size_position = content.len() - 12;
+        huge_resource[compressed_size_position..compressed_size_position + 4].copy_from_slice(&0xFFFFFFF0u32.to_le_bytes());
+        assert!(read_first_resource(huge_resource).is_err());
+        assert!(decompress_internal(&[0x10, 0xFB, 0x00, 0x00, 0x0C, 0xE0, b'a', b'b']).is_none());
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::tray_metadata::{self, TrayMetadata};
+use crate::utils::Outcome;
+use crate::{backup, constants, utils};
+
+struct SourceFile {
+    filename: String,
+    path: PathBuf,
+    hash: String,
+}
+
+struct DiffItem {
+    file_type: u32,
+    name: String,
+    fields: Vec<(u32, String)>,
+    files: BTreeMap<String, String>,
+}
+
+/// Compares two Tray folders, pack folders or snapshots item by item.
+pub fn diff(old_source: &str, new_source: &str, store: Option<&Path>) -> Outcome {
+    let old_items = match load_source(old_source, store) {
+        Some(files) => group_items(&files),
+        None => {
+            return Outcome::Failure;
+        }
+    };
+    let new_items = match load_source(new_source, store) {
+        Some(files) => group_items(&files),
+        None => {
+            return Outcome::Failure;
+        }
+    };
+    utils::print_info(format!("Comparing {} with {}", old_source, new_source));
+    let mut changes = 0;
+    for (id, old_item) in &old_items {
+        if !new_items.contains_key(id) {
+            utils::print_warning(format!("Removed: {}", describe_item(*id, old_item)));
+            changes += 1;
+        }
+    }
+    for (id, new_item) in &new_items {
+        match old_items.get(id) {
+            None => {
+                utils::print_success(format!("Added: {}", describe_item(*id, new_item)));
+                changes += 1;
+            },
+            Some(old_item) => {
+                let mut details: Vec<String> = Vec::new();
+                if old_item.name != new_item.name {
+                    details.push(format!("renamed from \"{}\"", old_item.name));
+                }
+                for (filename, hash) in &new_item.files {
+                    match old_item.files.get(filename) {
+                        None => {
+                            details.push(format!("file added: {}", filename));
+                        },
+                        Some(old_hash) if old_hash != hash => {
+                            details.push(format!("file modified: {}", filename));
+                        },
+                        Some(_) => {}
+                    }
+                }
+                for filename in old_item.files.keys() {
+                    if !new_item.files.contains_key(filename) {
+                        details.push(format!("file removed: {}", filename));
+                    }
+                }
+                details.extend(diff_fields(&old_item.fields, &new_item.fields));
+                if !details.is_empty() {
+                    let label = if old_item.name != new_item.name { "Renamed" } else { "Modified" };
+                    utils::print_info(format!("{}: {}", label, describe_item(*id, new_item)));
+                    for detail in details {
+                        utils::print_info(format!("    {}", detail));
+                    }
+                    changes += 1;
+                }
+            }
+        }
+    }
+    if changes == 0 {
+        utils::print_success(String::from("No differences found."));
+    } else {
+        utils::print_info(format!("{} gallery items differ.", changes));
+    }
+    return Outcome::Success;
+}
+
+fn describe_item(id: u64, item: &DiffItem) -> String {
+    let type_name = utils::get_type_folder_name(item.file_type).unwrap_or("unknown");
+    return format!("[{}] {} (0x{:x})", type_name, item.name, id);
+}
+
+/// Field level differences of the trayitem, repeated fields are compared as a whole. The name is left out, a new name
+/// is already reported as a rename.
+fn diff_fields(old_fields: &[(u32, String)], new_fields: &[(u32, String)]) -> Vec<String> {
+    let mut numbers: Vec<u32> = old_fields.iter().chain(new_fields.iter())
+        .map(|(number, _)| *number)
+        .filter(|number| *number != constants::TRAYITEM_NAME_FIELD)
+        .collect();
+    numbers.sort();
+    numbers.dedup();
+    let mut details: Vec<String> = Vec::new();
+    for number in numbers {
+        let old_values: Vec<&String> = old_fields.iter().filter(|(field, _)| *field == number).map(|(_, value)| value).collect();
+        let new_values: Vec<&String> = new_fields.iter().filter(|(field, _)| *field == number).map(|(_, value)| value).collect();
+        if old_values != new_values {
+            let field_name = match tray_metadata::get_field_name(number) {
+                Some(field_name) => String::from(field_name),
+                None => format!("field {}", number),
+            };
+            details.push(format!("{}: {} -> {}", field_name, join_values(&old_values), join_values(&new_values)));
+        }
+    }
+    return details;
+}
+
+fn join_values(values: &[&String]) -> String {
+    if values.is_empty() {
+        return String::from("(none)");
+    }
+    return values.iter().map(|value| value.as_str()).collect::<Vec<&str>>().join(", ");
+}
+
+/// A source is a folder (Tray or packed output, searched recursively) or the name of a snapshot in the backup store.
+/// Items packed as zip archives or bundles can't be compared, they have to be extracted or unpacked first.
+fn load_source(source: &str, store: Option<&Path>) -> Option<Vec<SourceFile>> {
+    let path = Path::new(source);
+    if path.is_file() && is_archive(path) {
+        utils::print_error(format!("{} is an archive, extract it or unpack it into a folder to compare it!", source));
+        return None;
+    }
+    if path.is_dir() {
+        let file_paths = utils::list_files_recursive(path);
+        if let Some(archive_path) = file_paths.iter().find(|file_path| is_archive(file_path)) {
+            utils::print_error(format!("{} holds archives like {}, only folder packs can be compared!", source, archive_path.display()));
+            return None;
+        }
+        let mut files: Vec<SourceFile> = Vec::new();
+        for file_path in file_paths {
+            if let Some(filename) = get_tray_filename(&file_path) {
+                match utils::hash_file(&file_path) {
+                    Ok(hash) => {
+                        files.push(SourceFile { filename, path: file_path, hash });
+                    },
+                    Err(_) => {
+                        utils::print_warning(format!("Couldn't read {}, skipping!", file_path.display()));
+                    }
+                }
+            }
+        }
+        return Some(files);
+    }
+    if let Some(store) = backup::get_backup_store(store) {
+        if let Some(snapshot) = backup::load_snapshot(&store, source) {
+            let files = snapshot.files.into_iter()
+                .filter(|file| get_tray_filename(Path::new(&file.filename)).is_some())
+                .filter_map(|file| Some(SourceFile {
+                    path: backup::get_object_path(&store, &file.hash)?,
+                    filename: file.filename,
+                    hash: file.hash,
+                }))
+                .collect();
+            return Some(files);
+        }
+    }
+    utils::print_error(format!("{} is neither a folder nor a snapshot name!", source));
+    return None;
+}
+
+fn is_archive(path: &Path) -> bool {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+    return extension == constants::BUNDLE_EXT || extension == "zip";
+}
+
+fn get_tray_filename(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?;
+    if !constants::TRAY_FILE_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+    return Some(String::from(path.file_name()?.to_str()?));
+}
+
+/// Groups files the same way packing does: trayitem, binaries with the same id, then the consecutive thumbnails.
+fn group_items(files: &[SourceFile]) -> BTreeMap<u64, DiffItem> {
+    let mut files_by_id: HashMap<u64, Vec<&SourceFile>> = HashMap::new();
+    for file in files {
+        if let Some((_, id)) = utils::extract_id_and_type(Path::new(&file.filename)) {
+            files_by_id.entry(id).or_default().push(file);
+        }
+    }
+    let mut items: BTreeMap<u64, DiffItem> = BTreeMap::new();
+    for file in files {
+        if !has_extension(&file.filename, constants::TRAYITEM_EXT) {
+            continue;
+        }
+        let (file_type, id) = match utils::extract_id_and_type(Path::new(&file.filename)) {
+            Some(id_and_type) => id_and_type,
+            None => {
+                continue;
+            }
+        };
+        let content = match fs::read(&file.path) {
+            Ok(content) => content,
+            Err(_) => {
+                utils::print_warning(format!("Couldn't read {}, skipping!", file.filename));
+                continue;
+            }
+        };
+        let name = TrayMetadata::parse(&content).map(|metadata| metadata.name).unwrap_or_default();
+        let mut item = DiffItem {
+            file_type,
+            name,
+            fields: tray_metadata::read_field_values(&content).unwrap_or_default(),
+            files: BTreeMap::new(),
+        };
+        for companion in &files_by_id[&id] {
+            if !has_extension(&companion.filename, constants::SGI_EXT) {
+                item.files.insert(companion.filename.clone(), companion.hash.clone());
+            }
+        }
+        let mut expected_id = id + 1;
+        loop {
+            let sgi_files: Vec<&&SourceFile> = files_by_id.get(&expected_id).map(|files| files.iter()
+                .filter(|sgi_file| has_extension(&sgi_file.filename, constants::SGI_EXT))
+                .collect()).unwrap_or_default();
+            if sgi_files.is_empty() {
+                break;
+            }
+            for sgi_file in sgi_files {
+                item.files.insert(sgi_file.filename.clone(), sgi_file.hash.clone());
+            }
+            expected_id += 1;
+        }
+        items.insert(id, item);
+    }
+    return items;
+}
+
+fn has_extension(filename: &str, extension: &str) -> bool {
+    return Path::new(filename).extension().and_then(|file_extension| file_extension.to_str()) == Some(extension);
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use directories::ProjectDirs;
+
+use crate::filters::TrayItemFilter;
+use crate::tray_files::{TrayFolderContent, TrayItem};
+use crate::tray_metadata::TrayMetadata;
+use crate::utils::Outcome;
+use crate::{constants, journal, naming, packing, prompts, utils};
+
+/// What two copies of the same item share: the same binaries, or the same type, name, creator and description.
+struct Fingerprint {
+    binaries: Option<String>,
+    metadata: Option<(u32, String, String, u64, String)>,
+}
+
+pub fn get_quarantine_folder() -> Option<PathBuf> {
+    let project_dirs = ProjectDirs::from("", "", constants::APPLICATION_NAME)?;
+    return Some(project_dirs.data_dir().join(constants::QUARANTINE_FOLDERNAME));
+}
+
+/// Groups the items saved more than once, keeps the newest of every group and moves the others to the quarantine folder.
+/// The quarantine folder is laid out like a packed output folder, so restore-output can put items back one by one.
+pub fn duplicates(tray_folder: &Path, filter: &TrayItemFilter, list_only: bool) -> Outcome {
+    let tray_content = packing::read_selected_tray_files(tray_folder, filter, false);
+    let tray_items: Vec<&TrayItem> = tray_content.trayitem_files.iter().collect();
+    let fingerprints = utils::parallel_map(&tray_items, |tray_item| get_fingerprint(&tray_content, tray_item));
+    let groups = group_duplicates(&tray_items, &fingerprints);
+    if groups.is_empty() {
+        utils::print_info(String::from("No duplicate gallery items found."));
+        return Outcome::NothingToDo;
+    }
+    let mut quarantined_items: Vec<&TrayItem> = Vec::new();
+    for (number, group) in groups.iter().enumerate() {
+        let same_binaries = group.iter().all(|index| fingerprints[*index].binaries.is_some() && fingerprints[*index].binaries == fingerprints[group[0]].binaries);
+        let reason = if same_binaries { "identical binaries" } else { "same name and metadata" };
+        utils::print_info(format!("Group {}: {} copies, {}", number + 1, group.len(), reason));
+        for (position, index) in group.iter().enumerate() {
+            let tray_item = tray_items[*index];
+            let modified: DateTime<Local> = DateTime::from(tray_item.modified);
+            let action = if position == 0 { "keep" } else { "quarantine" };
+            utils::print_info(format!("    {:<10} {} (0x{:x}) saved {}", action, tray_item.name, tray_item.id, modified.format("%Y-%m-%d %H:%M")));
+            if position > 0 {
+                quarantined_items.push(tray_item);
+            }
+        }
+    }
+    if list_only {
+        return Outcome::Success;
+    }
+    if !prompts::confirm(format!("{} older copies will be moved out of the Tray folder. Do you want to continue?", quarantined_items.len())) {
+        return Outcome::Failure;
+    }
+    let quarantine_folder = match get_quarantine_folder() {
+        Some(quarantine_folder) => quarantine_folder,
+        None => {
+            utils::print_error(String::from("Couldn't find the data directory to store the quarantined items!"));
+            return Outcome::Failure;
+        }
+    };
+    let entry_folder = quarantine_folder.join(utils::get_new_timestamp_name(&quarantine_folder, ""));
+    let mut moved_files: Vec<(&Path, PathBuf)> = Vec::new();
+    for tray_item in &quarantined_items {
+        let type_folder = utils::get_type_folder_name(tray_item.file_type).unwrap_or("unknown");
+        let item_folder = entry_folder.join(type_folder).join(format!("{} (0x{:x})", naming::sanitize(&tray_item.name), tray_item.id));
+        moved_files.push((&tray_item.path, item_folder.join(&tray_item.filename)));
+        for companion_file in tray_content.companion_files(tray_item) {
+            moved_files.push((&companion_file.path, item_folder.join(&companion_file.filename)));
+        }
+    }
+    let paths: Vec<&Path> = moved_files.iter().map(|(path, _)| *path).collect();
+    if let Err(e) = journal::record(&format!("quarantine of {} duplicate gallery items", quarantined_items.len()), &paths) {
+        utils::print_error(format!("{}, nothing was moved!", e));
+        return Outcome::Failure;
+    }
+    // Everything is copied before anything is deleted, a failed copy leaves the Tray folder untouched.
+    for (path, quarantined_path) in &moved_files {
+        let copied = quarantined_path.parent().map(fs::create_dir_all).unwrap_or(Ok(())).and_then(|_| fs::copy(path, quarantined_path));
+        if copied.is_err() {
+            utils::print_error(format!("Couldn't copy {} to the quarantine folder, nothing was moved!", path.display()));
+            let _ = fs::remove_dir_all(&entry_folder);
+            return Outcome::Failure;
+        }
+    }
+    let mut failed_files = 0;
+    for (path, _) in &moved_files {
+        if fs::remove_file(path).is_err() {
+            utils::print_error(format!("Couldn't delete {}!", path.display()));
+            failed_files += 1;
+        }
+    }
+    utils::print_success(format!("{} duplicates moved to {}, undo or restore-output puts them back.", quarantined_items.len(), entry_folder.display()));
+    if failed_files > 0 {
+        return Outcome::PartialFailure;
+    }
+    return Outcome::Success;
+}
+
+fn get_fingerprint(tray_content: &TrayFolderContent, tray_item: &TrayItem) -> Fingerprint {
+    let mut binary_hashes: Vec<String> = Vec::new();
+    for companion_file in tray_content.companion_files(tray_item) {
+        if companion_file.id != tray_item.id {
+            continue;
+        }
+        match utils::hash_file(&companion_file.path) {
+            Ok(hash) => {
+                let extension = companion_file.path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+                binary_hashes.push(format!("{}:{}", extension, hash));
+            },
+            Err(_) => {
+                utils::print_warning(format!("[{}] Couldn't read {}, its binaries won't be compared!", tray_item.name, companion_file.filename));
+                binary_hashes.clear();
+                break;
+            }
+        }
+    }
+    binary_hashes.sort();
+    let binaries = if binary_hashes.is_empty() { None } else { Some(utils::hash_bytes(binary_hashes.join(";").as_bytes())) };
+    let metadata = fs::read(&tray_item.path).ok().and_then(|content| get_metadata_key(tray_item.file_type, &content));
+    return Fingerprint { binaries, metadata };
+}
+
+fn get_metadata_key(file_type: u32, content: &[u8]) -> Option<(u32, String, String, u64, String)> {
+    return TrayMetadata::parse(content)
+        .filter(|metadata| !metadata.name.is_empty())
+        .map(|metadata| (file_type, metadata.name, metadata.creator_name, metadata.creator_id, metadata.description));
+}
+
+/// Items sharing binaries or metadata with any item of a group join it. Every group is sorted newest first.
+fn group_duplicates(tray_items: &[&TrayItem], fingerprints: &[Fingerprint]) -> Vec<Vec<usize>> {
+    let mut group_of: Vec<usize> = (0..tray_items.len()).collect();
+    let mut binaries_owner: HashMap<&String, usize> = HashMap::new();
+    let mut metadata_owner: HashMap<&(u32, String, String, u64, String), usize> = HashMap::new();
+    for (index, fingerprint) in fingerprints.iter().enumerate() {
+        let mut owners: Vec<usize> = Vec::new();
+        if let Some(binaries) = &fingerprint.binaries {
+            owners.push(*binaries_owner.entry(binaries).or_insert(index));
+        }
+        if let Some(metadata) = &fingerprint.metadata {
+            owners.push(*metadata_owner.entry(metadata).or_insert(index));
+        }
+        for owner in owners {
+            let (from, to) = (find_group(&group_of, index), find_group(&group_of, owner));
+            group_of[from] = to;
+        }
+    }
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for index in 0..tray_items.len() {
+        groups.entry(find_group(&group_of, index)).or_default().push(index);
+    }
+    let mut groups: Vec<Vec<usize>> = groups.into_values().filter(|group| group.len() > 1).collect();
+    for group in groups.iter_mut() {
+        group.sort_by(|a, b| tray_items[*b].modified.cmp(&tray_items[*a].modified).then(tray_items[*a].id.cmp(&tray_items[*b].id)));
+    }
+    groups.sort_by(|a, b| tray_items[a[0]].name.cmp(&tray_items[b[0]].name).then(tray_items[a[0]].id.cmp(&tray_items[b[0]].id)));
+    return groups;
+}
+
+fn find_group(group_of: &[usize], mut index: usize) -> usize {
+    while group_of[index] != index {
+        index = group_of[index];
+    }
+    return index;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::tray_metadata::tests::build_trayitem;
+
+    fn get_tray_item(id: u64, creator_name: &str, creator_id: u64) -> TrayItem {
+        return TrayItem {
+            path: PathBuf::from(format!("0x00000001!0x{:016x}.trayitem", id)),
+            name: String::from("Villa Rosa"),
+            creator_name: String::from(creator_name),
+            creator_id,
+            id,
+            file_type: constants::HOUSEHOLD_TRAYITEM_TYPE,
+            filename: format!("0x00000001!0x{:016x}.trayitem", id),
+            modified: SystemTime::UNIX_EPOCH,
+        };
+    }
+
+    #[test]
+    fn same_name_from_other_creators_isnt_a_duplicate() {
+        let creators = [("simmer42", 0x1234), ("builder7", 0x5678), ("simmer42", 0x1234)];
+        let tray_items: Vec<TrayItem> = creators.iter().enumerate().map(|(index, (creator_name, creator_id))| get_tray_item(0xaa12f12d6202c5 + 0x100 * index as u64, creator_name, *creator_id)).collect();
+        let fingerprints: Vec<Fingerprint> = creators.iter().map(|(creator_name, creator_id)| Fingerprint {
+            binaries: None,
+            metadata: get_metadata_key(constants::HOUSEHOLD_TRAYITEM_TYPE, &build_trayitem("Villa Rosa", *creator_id, creator_name)),
+        }).collect();
+        let tray_items: Vec<&TrayItem> = tray_items.iter().collect();
+        let mut groups = group_duplicates(&tray_items, &fingerprints);
+        assert_eq!(groups.len(), 1);
+        groups[0].sort();
+        assert_eq!(groups[0], vec![0, 2]);
+    }
+}
@@ -0,0 +1,235 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::cli::EditArgs;
+use crate::filters::TrayItemFilter;
+use crate::tray_files::TrayItem;
+use crate::tray_metadata::{self, FieldValue, TrayMetadata};
+use crate::utils::Outcome;
+use crate::{constants, journal, prompts, utils};
+
+pub struct MetadataChanges {
+    creator_name: Option<String>,
+    hashtags: Vec<String>,
+    replace_hashtags: bool,
+    rename: Option<(Regex, String)>,
+}
+
+/// New content of one trayitem, with the values shown in the preview.
+struct ItemEdit {
+    path: PathBuf,
+    content: Vec<u8>,
+    details: Vec<String>,
+}
+
+impl MetadataChanges {
+    pub fn new(args: &EditArgs) -> Result<MetadataChanges, String> {
+        let rename = match (&args.rename_from, &args.rename_to) {
+            (Some(pattern), Some(replacement)) => {
+                let regex = Regex::new(pattern).map_err(|e| format!("Invalid rename pattern \"{}\": {}", pattern, e))?;
+                Some((regex, replacement.clone()))
+            },
+            _ => None
+        };
+        let hashtags: Vec<String> = args.hashtag.iter().map(|hashtag| {
+            let hashtag = hashtag.trim().trim_start_matches('#');
+            return format!("#{}", hashtag);
+        }).collect();
+        if args.creator_name.is_none() && hashtags.is_empty() && !args.replace_hashtags && rename.is_none() {
+            return Err(String::from("Nothing to edit, use --creator-name, --hashtag, --replace-hashtags or --rename-from with --rename-to!"));
+        }
+        return Ok(MetadataChanges {
+            creator_name: args.creator_name.clone(),
+            hashtags,
+            replace_hashtags: args.replace_hashtags,
+            rename,
+        });
+    }
+
+    fn apply(&self, metadata: &TrayMetadata) -> Vec<(u32, FieldValue)> {
+        let mut values: Vec<(u32, FieldValue)> = Vec::new();
+        if let Some((regex, replacement)) = &self.rename {
+            let name = regex.replace_all(&metadata.name, replacement.as_str()).to_string();
+            if name != metadata.name {
+                values.push((constants::TRAYITEM_NAME_FIELD, FieldValue::Text(name)));
+            }
+        }
+        if let Some(creator_name) = &self.creator_name {
+            if *creator_name != metadata.creator_name {
+                values.push((constants::TRAYITEM_CREATOR_NAME_FIELD, FieldValue::Text(creator_name.clone())));
+            }
+        }
+        if !self.hashtags.is_empty() || self.replace_hashtags {
+            let description = self.edit_hashtags(&metadata.description);
+            if description != metadata.description {
+                values.push((constants::TRAYITEM_DESCRIPTION_FIELD, FieldValue::Text(description)));
+            }
+        }
+        return values;
+    }
+
+    /// Hashtags go at the end of the description, one space apart, the ones already there aren't added twice.
+    fn edit_hashtags(&self, description: &str) -> String {
+        let mut words: Vec<&str> = description.split(' ').collect();
+        if self.replace_hashtags {
+            words.retain(|word| !word.starts_with('#'));
+        }
+        let mut description = words.join(" ").trim_end().to_string();
+        for hashtag in &self.hashtags {
+            if description.split_whitespace().any(|word| word.eq_ignore_ascii_case(hashtag)) {
+                continue;
+            }
+            if !description.is_empty() {
+                description.push(' ');
+            }
+            description.push_str(hashtag);
+        }
+        return description;
+    }
+}
+
+/// Edits the trayitems of a Tray folder or of packed item folders, after showing every change that will be written.
+pub fn edit(folder: &Path, filter: &TrayItemFilter, changes: &MetadataChanges, dry_run: bool) -> Outcome {
+    if folder.extension().and_then(|extension| extension.to_str()) == Some(constants::BUNDLE_EXT) {
+        utils::print_error(String::from("Bundles can't be edited in place since it would break their checksums and signature, unpack it first!"));
+        return Outcome::Failure;
+    }
+    if !folder.is_dir() {
+        utils::print_error(format!("{} isn't a folder!", folder.display()));
+        return Outcome::Failure;
+    }
+    let mut edits: Vec<ItemEdit> = Vec::new();
+    for path in utils::list_files_recursive(folder) {
+        if path.extension().and_then(|extension| extension.to_str()) != Some(constants::TRAYITEM_EXT) {
+            continue;
+        }
+        let tray_item = match TrayItem::new(&path) {
+            Some(tray_item) => tray_item,
+            None => {
+                continue;
+            }
+        };
+        if !filter.matches(&tray_item) {
+            continue;
+        }
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(_) => {
+                utils::print_warning(format!("Couldn't read {}, skipping!", path.display()));
+                continue;
+            }
+        };
+        if let Some(item_edit) = prepare_edit(&tray_item, &content, changes) {
+            edits.push(item_edit);
+        }
+    }
+    if edits.is_empty() {
+        utils::print_info(String::from("No gallery item needs to be changed."));
+        return Outcome::NothingToDo;
+    }
+    for item_edit in &edits {
+        utils::print_info(format!("{}", item_edit.path.strip_prefix(folder).unwrap_or(&item_edit.path).display()));
+        for detail in &item_edit.details {
+            utils::print_info(format!("    {}", detail));
+        }
+    }
+    if dry_run {
+        utils::print_info(format!("{} gallery items would be changed, nothing was written.", edits.len()));
+        return Outcome::Success;
+    }
+    if !prompts::confirm(format!("{} gallery items will be changed. Do you want to continue?", edits.len())) {
+        return Outcome::Failure;
+    }
+    let paths: Vec<&Path> = edits.iter().map(|item_edit| item_edit.path.as_path()).collect();
+    if let Err(e) = journal::record(&format!("edit of {} gallery items in {}", edits.len(), folder.display()), &paths) {
+        utils::print_error(format!("{}, nothing was changed!", e));
+        return Outcome::Failure;
+    }
+    // The whole batch is written or none of it, so a failure never leaves some items edited and others not.
+    let files: Vec<(&Path, &[u8])> = edits.iter().map(|item_edit| (item_edit.path.as_path(), item_edit.content.as_slice())).collect();
+    match utils::write_files_atomically(&files) {
+        Ok(_) => {
+            utils::print_success(format!("{} gallery items changed.", edits.len()));
+            return Outcome::Success;
+        },
+        Err(e) => {
+            utils::print_error(format!("{}, the gallery items couldn't be changed!", e));
+            return Outcome::Failure;
+        }
+    }
+}
+
+fn prepare_edit(tray_item: &TrayItem, content: &[u8], changes: &MetadataChanges) -> Option<ItemEdit> {
+    let metadata = TrayMetadata::parse(content)?;
+    let values = changes.apply(&metadata);
+    if values.is_empty() {
+        return None;
+    }
+    let mut details: Vec<String> = Vec::new();
+    for (number, value) in &values {
+        let old_value = match *number {
+            constants::TRAYITEM_NAME_FIELD => &metadata.name,
+            constants::TRAYITEM_CREATOR_NAME_FIELD => &metadata.creator_name,
+            _ => &metadata.description
+        };
+        if let FieldValue::Text(new_value) = value {
+            let field_name = tray_metadata::get_field_name(*number).unwrap_or("field");
+            details.push(format!("{}: \"{}\" -> \"{}\"", field_name, old_value, new_value));
+        }
+    }
+    match tray_metadata::set_fields(content, &values) {
+        Some(new_content) => {
+            return Some(ItemEdit { path: tray_item.path.clone(), content: new_content, details });
+        },
+        None => {
+            utils::print_warning(format!("[{}] Couldn't encode the new metadata, skipping!", tray_item.name));
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::protobuf;
+    use crate::tray_metadata::tests::build_trayitem;
+
+    /// Raw bytes of every top level field of the trayitem, by field number.
+    fn get_field_bytes(content: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        let message = &content[constants::TRAYITEM_HEADER_SIZE..];
+        return protobuf::read_field_spans(message).unwrap().iter().map(|span| (span.field.number, message[span.start..span.end].to_vec())).collect();
+    }
+
+    #[test]
+    fn creator_name_edit_keeps_the_other_fields() {
+        let content = build_trayitem("Villa Rosa", 0x1234_5678_9abc, "simmer42");
+        let tray_item = TrayItem {
+            path: PathBuf::from("0x00000001!0x00aa12f12d6202c5.trayitem"),
+            name: String::from("Villa Rosa"),
+            creator_name: String::from("simmer42"),
+            creator_id: 0x1234_5678_9abc,
+            id: 0xaa12f12d6202c5,
+            file_type: constants::HOUSEHOLD_TRAYITEM_TYPE,
+            filename: String::from("0x00000001!0x00aa12f12d6202c5.trayitem"),
+            modified: SystemTime::UNIX_EPOCH,
+        };
+        let changes = MetadataChanges { creator_name: Some(String::from("Someone Else")), hashtags: Vec::new(), replace_hashtags: false, rename: None };
+        let item_edit = prepare_edit(&tray_item, &content, &changes).unwrap();
+        let metadata = TrayMetadata::parse(&item_edit.content).unwrap();
+        assert_eq!(metadata.creator_name, "Someone Else");
+        assert_eq!(metadata.creator_id, 0x1234_5678_9abc);
+        let old_fields = get_field_bytes(&content);
+        let new_fields = get_field_bytes(&item_edit.content);
+        assert_eq!(old_fields.len(), new_fields.len());
+        for (old_field, new_field) in old_fields.iter().zip(&new_fields) {
+            assert_eq!(old_field.0, new_field.0);
+            if old_field.0 != constants::TRAYITEM_CREATOR_NAME_FIELD {
+                assert_eq!(old_field.1, new_field.1);
+            }
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use regex::{Regex, RegexBuilder};
+
+use crate::cli::FilterArgs;
+use crate::tray_files::TrayItem;
+
+pub struct TrayItemFilter {
+    types: Vec<u32>,
+    name: Option<Regex>,
+    creators: Vec<String>,
+    ids: Vec<u64>,
+    modified_since: Option<SystemTime>,
+}
+
+impl TrayItemFilter {
+    pub fn new(args: &FilterArgs) -> Result<TrayItemFilter, String> {
+        let name = match &args.name {
+            Some(pattern) => Some(parse_name_pattern(pattern)?),
+            None => None
+        };
+        return Ok(TrayItemFilter {
+            types: args.item_type.iter().map(|item_type| item_type.file_type()).collect(),
+            name,
+            creators: args.creator.iter().map(|creator| creator.to_lowercase()).collect(),
+            ids: args.id.clone(),
+            modified_since: args.modified_since,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.types.is_empty() && self.name.is_none() && self.creators.is_empty() && self.ids.is_empty() && self.modified_since.is_none();
+    }
+
+    pub fn matches(&self, tray_item: &TrayItem) -> bool {
+        if !self.types.is_empty() && !self.types.contains(&tray_item.file_type) {
+            return false;
+        }
+        if let Some(name) = &self.name {
+            if !name.is_match(&tray_item.name) {
+                return false;
+            }
+        }
+        if !self.creators.is_empty() && !self.creators.contains(&tray_item.creator_name.to_lowercase()) {
+            return false;
+        }
+        if !self.ids.is_empty() && !self.ids.contains(&tray_item.id) {
+            return false;
+        }
+        if let Some(modified_since) = self.modified_since {
+            if tray_item.modified < modified_since {
+                return false;
+            }
+        }
+        return true;
+    }
+}
+
+/// Name patterns are case insensitive globs (`*` and `?`), or regular expressions when prefixed with `re:`.
+fn parse_name_pattern(pattern: &str) -> Result<Regex, String> {
+    let expression = match pattern.strip_prefix("re:") {
+        Some(expression) => String::from(expression),
+        None => {
+            let mut expression = String::from("^");
+            for character in pattern.chars() {
+                match character {
+                    '*' => {
+                        expression.push_str(".*");
+                    },
+                    '?' => {
+                        expression.push('.');
+                    },
+                    _ => {
+                        expression.push_str(&regex::escape(&character.to_string()));
+                    }
+                }
+            }
+            expression.push('$');
+            expression
+        }
+    };
+    match RegexBuilder::new(&expression).case_insensitive(true).build() {
+        Ok(regex) => {
+            return Ok(regex);
+        },
+        Err(e) => {
+            return Err(format!("Invalid name pattern \"{}\": {}", pattern, e));
+        }
+    }
+}
+
+/// Ids are hexadecimal like in the Tray file names, the 0x prefix is optional so plain digits are hexadecimal too.
+pub fn parse_id(value: &str) -> Result<u64, String> {
+    let digits = value.strip_prefix("0x").or(value.strip_prefix("0X")).unwrap_or(value);
+    if !digits.chars().all(|character| character.is_ascii_hexdigit()) {
+        return Err(format!("\"{}\" is not a valid hexadecimal id", value));
+    }
+    match u64::from_str_radix(digits, 16) {
+        Ok(id) => {
+            return Ok(id);
+        },
+        Err(_) => {
+            return Err(format!("\"{}\" is not a valid hexadecimal id", value));
+        }
+    }
+}
+
+/// Accepts a plain date (`2024-05-31`, local midnight) or a full RFC 3339 timestamp.
+pub fn parse_date(value: &str) -> Result<SystemTime, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        if let Some(midnight) = date.and_hms_opt(0, 0, 0) {
+            if let Some(local) = Local.from_local_datetime(&midnight).earliest() {
+                return Ok(SystemTime::from(local));
+            }
+        }
+    }
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(timestamp) => {
+            return Ok(SystemTime::from(timestamp));
+        },
+        Err(_) => {
+            return Err(format!("\"{}\" is not a valid date, use YYYY-MM-DD or an RFC 3339 timestamp", value));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::cli::ItemType;
+    use crate::constants;
+
+    fn get_tray_item(name: &str, creator_name: &str) -> TrayItem {
+        return TrayItem {
+            path: PathBuf::from("0x00000001!0x00aa12f12d6202c5.trayitem"),
+            name: String::from(name),
+            creator_name: String::from(creator_name),
+            creator_id: 0x1234_5678_9abc,
+            id: 0xaa12f12d6202c5,
+            file_type: constants::HOUSEHOLD_TRAYITEM_TYPE,
+            filename: String::from("0x00000001!0x00aa12f12d6202c5.trayitem"),
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_156_800),
+        };
+    }
+
+    fn get_filter(name: Option<&str>, creators: &[&str]) -> TrayItemFilter {
+        let args = FilterArgs {
+            item_type: Vec::new(),
+            name: name.map(String::from),
+            creator: creators.iter().map(|creator| String::from(*creator)).collect(),
+            id: Vec::new(),
+            modified_since: None,
+        };
+        return TrayItemFilter::new(&args).unwrap();
+    }
+
+    #[test]
+    fn name_globs_match_the_whole_name() {
+        let tray_item = get_tray_item("Villa Rosa", "simmer42");
+        for pattern in ["villa*", "*ROSA", "Vill? Rosa", "Villa Rosa", "*"] {
+            assert!(get_filter(Some(pattern), &[]).matches(&tray_item), "{} didn't match", pattern);
+        }
+        for pattern in ["Rosa", "Villa", "Villa.Rosa", "V[i]lla Rosa", "Villa Rosa?"] {
+            assert!(!get_filter(Some(pattern), &[]).matches(&tray_item), "{} matched", pattern);
+        }
+    }
+
+    #[test]
+    fn name_regexes_match_anywhere() {
+        let tray_item = get_tray_item("Villa Rosa", "simmer42");
+        for pattern in ["re:rosa", "re:^v.*a$", "re:Vill[ae] "] {
+            assert!(get_filter(Some(pattern), &[]).matches(&tray_item), "{} didn't match", pattern);
+        }
+        assert!(!get_filter(Some("re:^rosa"), &[]).matches(&tray_item));
+        let args = FilterArgs { item_type: Vec::new(), name: Some(String::from("re:(")), creator: Vec::new(), id: Vec::new(), modified_since: None };
+        assert!(TrayItemFilter::new(&args).is_err());
+    }
+
+    #[test]
+    fn creators_are_case_insensitive() {
+        let tray_item = get_tray_item("Villa Rosa", "Simmer42");
+        assert!(get_filter(None, &["SIMMER42"]).matches(&tray_item));
+        assert!(get_filter(None, &["builder7", "simmer42"]).matches(&tray_item));
+        assert!(!get_filter(None, &["simmer4"]).matches(&tray_item));
+    }
+
+    #[test]
+    fn every_filter_must_match() {
+        let tray_item = get_tray_item("Villa Rosa", "simmer42");
+        let mut args = FilterArgs {
+            item_type: vec![ItemType::Household],
+            name: Some(String::from("villa*")),
+            creator: vec![String::from("simmer42")],
+            id: vec![0xaa12f12d6202c5],
+            modified_since: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_156_800)),
+        };
+        assert!(TrayItemFilter::new(&args).unwrap().matches(&tray_item));
+        args.item_type = vec![ItemType::Lot, ItemType::Room];
+        assert!(!TrayItemFilter::new(&args).unwrap().matches(&tray_item));
+        args.item_type = Vec::new();
+        args.id = vec![0xaa12f12d6202c6];
+        assert!(!TrayItemFilter::new(&args).unwrap().matches(&tray_item));
+        args.id = Vec::new();
+        args.modified_since = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_156_801));
+        assert!(!TrayItemFilter::new(&args).unwrap().matches(&tray_item));
+    }
+
+    #[test]
+    fn parse_id_reads_hexadecimal_with_or_without_prefix() {
+        assert_eq!(parse_id("0x00aa12f12d6202c5"), Ok(0xaa12f12d6202c5));
+        assert_eq!(parse_id("0XAA12F12D6202C5"), Ok(0xaa12f12d6202c5));
+        assert_eq!(parse_id("aa12f12d6202c5"), Ok(0xaa12f12d6202c5));
+        assert_eq!(parse_id("1234"), Ok(0x1234));
+        assert_eq!(parse_id("0xffffffffffffffff"), Ok(u64::MAX));
+        for value in ["", "0x", "0x0x12", "+12", "-12", "12g", "0x1ffffffffffffffff", " 12"] {
+            assert!(parse_id(value).is_err(), "{} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn parse_date_reads_local_dates_and_timestamps() {
+        let local_midnight = Local.with_ymd_and_hms(2024, 5, 31, 0, 0, 0).earliest().unwrap();
+        assert_eq!(parse_date("2024-05-31"), Ok(SystemTime::from(local_midnight)));
+        assert_eq!(parse_date("2024-05-31T12:00:00Z"), Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_156_800)));
+        assert_eq!(parse_date("2024-05-31T14:00:00+02:00"), Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_156_800)));
+        for value in ["", "31/05/2024", "2024-02-30", "2024-05-31 12:00", "yesterday"] {
+            assert!(parse_date(value).is_err(), "{} was accepted", value);
+        }
+    }
+}
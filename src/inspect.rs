@@ -0,0 +1,191 @@
+use std::fs;
+use std::path::Path;
+
+use crate::tray_files::{TrayItem, TrayItemFile};
+use crate::utils::Outcome;
+use crate::{constants, filters, protobuf, tray_metadata, utils};
+
+/// Bytes shown for the header of every companion file.
+const HEADER_PREVIEW_SIZE: usize = 16;
+
+/// Shows everything known about one item, given its hexadecimal id or the path of any of its files: the parts of the
+/// file names, the trayitem fields with their offsets, the companion files and a hex dump of the trayitem.
+pub fn inspect(target: &str, tray_override: Option<&Path>) -> Outcome {
+    let path = Path::new(target);
+    let (tray_folder, id, path) = if path.is_file() {
+        let path = std::path::absolute(path).unwrap_or(path.to_path_buf());
+        (path.parent().map(Path::to_path_buf), None, Some(path))
+    } else {
+        match filters::parse_id(target) {
+            Ok(id) => (utils::get_tray_folder(tray_override), Some(id), None),
+            Err(_) => {
+                utils::print_error(format!("{} is neither a file nor a hexadecimal item id!", target));
+                return Outcome::Failure;
+            }
+        }
+    };
+    let tray_folder = match tray_folder {
+        Some(tray_folder) => tray_folder,
+        None => {
+            return Outcome::Failure;
+        }
+    };
+    let tray_content = utils::read_tray_files(&tray_folder);
+    let tray_item = tray_content.trayitem_files.iter().find(|tray_item| {
+        if let Some(path) = &path {
+            return tray_item.path == *path || tray_content.companion_files(tray_item).iter().any(|companion_file| companion_file.path == *path);
+        }
+        return Some(tray_item.id) == id;
+    });
+    match (tray_item, path) {
+        (Some(tray_item), _) => {
+            inspect_item(tray_item, &tray_content.companion_files(tray_item));
+            return Outcome::Success;
+        },
+        (None, Some(path)) => {
+            // A trayitem the game or the parser can't read is exactly the one worth looking at.
+            utils::print_warning(format!("{} isn't part of a readable gallery item, showing the file alone.", path.display()));
+            inspect_file(&path);
+            return Outcome::Success;
+        },
+        (None, None) => {
+            utils::print_error(format!("There is no gallery item with id 0x{:x} in {}!", id.unwrap_or(0), tray_folder.display()));
+            return Outcome::Failure;
+        }
+    }
+}
+
+fn inspect_item(tray_item: &TrayItem, companion_files: &[&TrayItemFile]) {
+    utils::print_info(format!("{} (0x{:x}) by {} (0x{:x})", tray_item.name, tray_item.id, tray_item.creator_name, tray_item.creator_id));
+    inspect_file(&tray_item.path);
+    utils::print_info(format!("Companion files: {}", companion_files.len()));
+    for companion_file in companion_files {
+        let content = fs::read(&companion_file.path).unwrap_or_default();
+        utils::print_info(format!("    {}  {}", companion_file.filename, describe_filename(&companion_file.path)));
+        let header = &content[..content.len().min(HEADER_PREVIEW_SIZE)];
+        utils::print_info(format!("        {} bytes, header: {}", content.len(), format_bytes(header)));
+    }
+}
+
+fn inspect_file(path: &Path) {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(_) => {
+            utils::print_error(format!("Couldn't read {}!", path.display()));
+            return;
+        }
+    };
+    utils::print_info(format!("File: {}", path.display()));
+    utils::print_info(format!("    {}, {} bytes", describe_filename(path), content.len()));
+    if path.extension().and_then(|extension| extension.to_str()) == Some(constants::TRAYITEM_EXT) {
+        print_trayitem_fields(&content);
+    }
+    utils::print_info(String::from("Hex dump:"));
+    for line in format_hex_dump(&content) {
+        utils::print_info(format!("    {}", line));
+    }
+}
+
+/// Type and instance encoded in a Tray file name, `0x<type>!0x<instance>.<extension>`.
+fn describe_filename(path: &Path) -> String {
+    match utils::extract_id_and_type(path) {
+        Some((file_type, id)) => {
+            let type_name = match utils::get_type_folder_name(file_type) {
+                Some(type_name) => format!(" ({})", type_name),
+                None => String::new()
+            };
+            return format!("type 0x{:08x}{}, instance 0x{:016x}", file_type, type_name, id);
+        },
+        None => {
+            return String::from("not a Tray file name");
+        }
+    }
+}
+
+/// Offsets are from the start of the file, so they can be found in the hex dump below.
+fn print_trayitem_fields(content: &[u8]) {
+    let header_size = constants::TRAYITEM_HEADER_SIZE;
+    if content.len() < header_size {
+        utils::print_warning(String::from("The file is shorter than the trayitem header!"));
+        return;
+    }
+    let message_length = u32::from_le_bytes([content[4], content[5], content[6], content[7]]) as usize;
+    let length_check = if message_length == content.len() - header_size { "matches the file" } else { "doesn't match the file" };
+    utils::print_info(format!("Header: {}, message length {} ({})", format_bytes(&content[..4]), message_length, length_check));
+    let spans = match protobuf::read_field_spans(&content[header_size..]) {
+        Some(spans) => spans,
+        None => {
+            utils::print_warning(String::from("The message isn't valid protobuf, see the hex dump."));
+            return;
+        }
+    };
+    utils::print_info(format!("Fields: {}", spans.len()));
+    for span in &spans {
+        let field = &span.field;
+        let offset = header_size + span.start;
+        let name = tray_metadata::get_field_name(field.number);
+        utils::print_info(format!("    @0x{:04x} {:>3} {:<13} {:<8} {}", offset, field.number, name.unwrap_or("unknown"), protobuf::get_wire_type_name(&field.value), protobuf::format_value(&field.value)));
+        if name.is_none() {
+            utils::print_info(format!("          raw: {}", format_bytes(&content[offset..header_size + span.end])));
+        }
+    }
+}
+
+/// Prints any file, or a part of it, as a protobuf tree. Trayitems and binaries start with the same 8 byte header,
+/// it is skipped when its length matches the file and no offset is given.
+pub fn dump(path: &Path, offset: Option<usize>, length: Option<usize>, max_depth: usize) -> Outcome {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(_) => {
+            utils::print_error(format!("Couldn't read {}!", path.display()));
+            return Outcome::Failure;
+        }
+    };
+    let header_size = constants::TRAYITEM_HEADER_SIZE;
+    let start = match offset {
+        Some(offset) => offset,
+        None => {
+            let has_header = content.len() >= header_size && u32::from_le_bytes([content[4], content[5], content[6], content[7]]) as usize == content.len() - header_size;
+            if has_header { header_size } else { 0 }
+        }
+    };
+    let end = match length {
+        Some(length) => start.saturating_add(length),
+        None => content.len()
+    };
+    let message = match content.get(start..end) {
+        Some(message) => message,
+        None => {
+            utils::print_error(format!("{} is only {} bytes long!", path.display(), content.len()));
+            return Outcome::Failure;
+        }
+    };
+    utils::print_info(format!("{} bytes 0x{:x}..0x{:x}:", path.display(), start, end));
+    for line in protobuf::dump(message, start, max_depth) {
+        utils::print_info(format!("    {}", line));
+    }
+    return Outcome::Success;
+}
+
+/// Offsets are shown in hexadecimal, so they are accepted that way with a 0x prefix.
+pub fn parse_offset(value: &str) -> Result<usize, String> {
+    let parsed = match value.strip_prefix("0x").or(value.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse::<usize>()
+    };
+    return parsed.map_err(|_| format!("\"{}\" is not a valid offset", value));
+}
+
+fn format_bytes(bytes: &[u8]) -> String {
+    return bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ");
+}
+
+/// Classic 16 bytes per line dump with the offset and the printable characters.
+pub fn format_hex_dump(content: &[u8]) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for (index, chunk) in content.chunks(16).enumerate() {
+        let text: String = chunk.iter().map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' }).collect();
+        lines.push(format!("{:08x}  {:<47}  {}", index * 16, format_bytes(chunk), text));
+    }
+    return lines;
+}
@@ -0,0 +1,337 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli::ConflictChoice;
+use crate::custom_content;
+use crate::filters::TrayItemFilter;
+use crate::tray_files::TrayItem;
+use crate::tray_metadata::{self, FieldValue};
+use crate::utils::Outcome;
+use crate::{constants, journal, prompts, utils};
+
+/// Item folder of a packed output folder, with the content of every file it installs.
+struct PackedItem {
+    tray_item: TrayItem,
+    folder: PathBuf,
+    files: Vec<(String, Vec<u8>)>,
+    packages: Vec<(PathBuf, Vec<u8>)>,
+}
+
+/// Installs the item folders of a packed output folder back into the Tray folder. Items whose id is already used there
+/// are skipped, overwritten or installed as a copy with a new id, as chosen.
+pub fn restore_output(output_folder: &Path, tray_folder: &Path, mods_override: Option<&Path>, filter: &TrayItemFilter, on_conflict: ConflictChoice) -> Outcome {
+    if !output_folder.is_dir() {
+        utils::print_error(format!("{} isn't a folder!", output_folder.display()));
+        return Outcome::Failure;
+    }
+    let mut packed_items: Vec<PackedItem> = Vec::new();
+    let mut invalid_items = 0;
+    for folder in find_item_folders(output_folder) {
+        match read_packed_item(&folder) {
+            Ok(packed_item) => {
+                if filter.matches(&packed_item.tray_item) {
+                    packed_items.push(packed_item);
+                }
+            },
+            Err(e) => {
+                utils::print_warning(format!("{} {}, skipping!", folder.display(), e));
+                invalid_items += 1;
+            }
+        }
+    }
+    if packed_items.is_empty() {
+        utils::print_info(format!("There are no gallery items to restore in {}.", output_folder.display()));
+        if invalid_items > 0 {
+            return Outcome::Failure;
+        }
+        return Outcome::NothingToDo;
+    }
+    let tray_content = utils::read_tray_files(tray_folder);
+    // Copies get ids above every id in use, thumbnails included, so they can't collide with anything.
+    let mut next_id = tray_content.trayitem_files.iter().map(|tray_item| tray_item.id)
+        .chain(tray_content.unknown_files.iter().chain(tray_content.sgi_files.iter()).map(|tray_file| tray_file.id))
+        .chain(packed_items.iter().map(|packed_item| packed_item.tray_item.id))
+        .max().unwrap_or(0) + 1;
+    let mut mods_folder: Option<PathBuf> = None;
+    if packed_items.iter().any(|packed_item| !packed_item.packages.is_empty()) {
+        mods_folder = custom_content::get_mods_folder(tray_folder, mods_override);
+        if mods_folder.is_none() {
+            utils::print_warning(String::from("The custom content of the items won't be installed!"));
+        }
+    }
+    let mut writes: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+    let mut deletes: Vec<PathBuf> = Vec::new();
+    let mut planned_ids: HashSet<u64> = HashSet::new();
+    let (mut installed, mut replaced, mut copies, mut skipped, mut up_to_date) = (0, 0, 0, 0, 0);
+    for packed_item in &packed_items {
+        let tray_item = &packed_item.tray_item;
+        if !planned_ids.insert(tray_item.id) {
+            utils::print_warning(format!("[{}] 0x{:x} is restored from another folder already, skipping {}!", tray_item.name, tray_item.id, packed_item.folder.display()));
+            skipped += 1;
+            continue;
+        }
+        let existing_item = tray_content.trayitem_files.iter().find(|existing_item| existing_item.id == tray_item.id);
+        let mut existing_paths: Vec<PathBuf> = Vec::new();
+        if let Some(existing_item) = existing_item {
+            existing_paths.push(existing_item.path.clone());
+            existing_paths.extend(tray_content.companion_files(existing_item).iter().map(|companion_file| companion_file.path.clone()));
+        }
+        let target_paths: Vec<PathBuf> = packed_item.files.iter().map(|(filename, _)| tray_folder.join(filename)).collect();
+        if existing_item.is_none() && !target_paths.iter().any(|target_path| target_path.exists()) {
+            utils::print_info(format!("[{}] Will be installed.", tray_item.name));
+            writes.extend(target_paths.into_iter().zip(packed_item.files.iter().map(|(_, content)| content.clone())));
+            installed += 1;
+        } else if is_installed(packed_item, &target_paths, &existing_paths) {
+            utils::print_debug(format!("[{}] Already installed.", tray_item.name));
+            up_to_date += 1;
+            continue;
+        } else {
+            let choice = match on_conflict {
+                ConflictChoice::Ask => {
+                    let existing_name = existing_item.map(|existing_item| format!("\"{}\"", existing_item.name)).unwrap_or(String::from("other files"));
+                    match ask_conflict_choice(format!("[{}] 0x{:x} is already used by {} in the Tray folder. What should be done?", tray_item.name, tray_item.id, existing_name)) {
+                        Some(choice) => choice,
+                        None => {
+                            utils::print_warning(String::from("Cancelled, nothing was restored."));
+                            return Outcome::Failure;
+                        }
+                    }
+                },
+                choice => choice
+            };
+            match choice {
+                ConflictChoice::Overwrite => {
+                    utils::print_info(format!("[{}] Will replace the item already in the Tray folder.", tray_item.name));
+                    deletes.extend(existing_paths.into_iter().filter(|existing_path| !target_paths.contains(existing_path)));
+                    writes.extend(target_paths.into_iter().zip(packed_item.files.iter().map(|(_, content)| content.clone())));
+                    replaced += 1;
+                },
+                ConflictChoice::Remap => {
+                    match remap_files(packed_item, next_id) {
+                        Some(files) => {
+                            utils::print_info(format!("[{}] Will be installed as a copy with id 0x{:x}.", tray_item.name, next_id));
+                            for (filename, content) in files {
+                                if let Some((_, id)) = utils::extract_id_and_type(Path::new(&filename)) {
+                                    next_id = next_id.max(id + 1);
+                                }
+                                writes.push((tray_folder.join(filename), content));
+                            }
+                            copies += 1;
+                        },
+                        None => {
+                            utils::print_warning(format!("[{}] Couldn't give the item a new id, skipping!", tray_item.name));
+                            skipped += 1;
+                            continue;
+                        }
+                    }
+                },
+                _ => {
+                    utils::print_info(format!("[{}] Skipped, the Tray folder keeps its own item.", tray_item.name));
+                    skipped += 1;
+                    continue;
+                }
+            }
+        }
+        if let Some(mods_folder) = &mods_folder {
+            for (relative_path, content) in &packed_item.packages {
+                let target_path = mods_folder.join(relative_path);
+                if writes.iter().any(|(path, _)| *path == target_path) {
+                    continue;
+                }
+                match fs::read(&target_path) {
+                    Ok(existing_content) => {
+                        if existing_content != *content {
+                            utils::print_warning(format!("[{}] {} is different in the Mods folder, keeping the installed one.", tray_item.name, relative_path.display()));
+                        }
+                    },
+                    Err(_) => {
+                        writes.push((target_path, content.clone()));
+                    }
+                }
+            }
+        }
+    }
+    if writes.is_empty() && deletes.is_empty() {
+        utils::print_info(format!("Nothing to restore: {} items already installed, {} skipped.", up_to_date, skipped));
+        if invalid_items > 0 {
+            return Outcome::Failure;
+        }
+        return Outcome::NothingToDo;
+    }
+    utils::print_info(format!("{} new items, {} replaced, {} copies, {} already installed, {} skipped.", installed, replaced, copies, up_to_date, skipped));
+    if replaced > 0 && on_conflict != ConflictChoice::Ask && !prompts::confirm(format!("{} items of the Tray folder will be replaced. Do you want to continue?", replaced)) {
+        return Outcome::Failure;
+    }
+    let mut journal_paths: Vec<&Path> = writes.iter().map(|(path, _)| path.as_path()).collect();
+    journal_paths.extend(deletes.iter().map(|path| path.as_path()));
+    if let Err(e) = journal::record(&format!("restore-output {}", output_folder.display()), &journal_paths) {
+        utils::print_error(format!("{}, nothing was restored!", e));
+        return Outcome::Failure;
+    }
+    let staged_files: Vec<(&Path, &[u8])> = writes.iter().map(|(path, content)| (path.as_path(), content.as_slice())).collect();
+    if let Err(e) = utils::write_files_atomically(&staged_files) {
+        utils::print_error(format!("{}, nothing was restored!", e));
+        return Outcome::Failure;
+    }
+    let mut failed_files = 0;
+    for path in &deletes {
+        if fs::remove_file(path).is_err() {
+            utils::print_error(format!("Couldn't delete {}!", path.display()));
+            failed_files += 1;
+        }
+    }
+    let restored = installed + replaced + copies;
+    utils::print_success(format!("{} gallery items restored from {}.", restored, output_folder.display()));
+    if failed_files > 0 {
+        return Outcome::PartialFailure;
+    }
+    return Outcome::from_counts(restored, invalid_items);
+}
+
+fn ask_conflict_choice(message: String) -> Option<ConflictChoice> {
+    let choices = ["Skip it", "Overwrite the item in the Tray folder", "Install it as a copy with a new id"];
+    match prompts::select(message, &choices)? {
+        1 => {
+            return Some(ConflictChoice::Overwrite);
+        },
+        2 => {
+            return Some(ConflictChoice::Remap);
+        },
+        _ => {
+            return Some(ConflictChoice::Skip);
+        }
+    }
+}
+
+/// Folders holding a trayitem, whatever naming template was used to pack them.
+fn find_item_folders(output_folder: &Path) -> BTreeSet<PathBuf> {
+    let mut folders: BTreeSet<PathBuf> = BTreeSet::new();
+    for path in utils::list_files_recursive(output_folder) {
+        if path.extension().and_then(|extension| extension.to_str()) != Some(constants::TRAYITEM_EXT) {
+            continue;
+        }
+        if path.components().any(|component| component.as_os_str() == constants::STAGING_FOLDERNAME) {
+            continue;
+        }
+        if let Some(folder) = path.parent() {
+            folders.insert(folder.to_path_buf());
+        }
+    }
+    return folders;
+}
+
+/// Checks that the folder holds exactly one item: one trayitem, its binaries with the same id and its thumbnails.
+fn read_packed_item(folder: &Path) -> Result<PackedItem, String> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(folder).map_err(|_| String::from("can't be read"))?
+        .flatten().map(|entry| entry.path()).filter(|path| path.is_file()).collect();
+    paths.sort();
+    let mut trayitem_path: Option<&Path> = None;
+    for path in &paths {
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+        if !constants::TRAY_FILE_EXTENSIONS.contains(&extension) {
+            return Err(format!("holds {} which isn't a Tray file", path.display()));
+        }
+        if extension == constants::TRAYITEM_EXT {
+            if trayitem_path.is_some() {
+                return Err(String::from("holds several trayitems"));
+            }
+            trayitem_path = Some(path);
+        }
+    }
+    let tray_item = trayitem_path.and_then(TrayItem::new).ok_or(String::from("has no readable trayitem"))?;
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut has_binary = false;
+    for path in &paths {
+        let (_, id) = utils::extract_id_and_type(path).ok_or(format!("holds {} which has an invalid name", path.display()))?;
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+        let belongs = if extension == constants::SGI_EXT { id > tray_item.id } else { id == tray_item.id };
+        if !belongs {
+            return Err(format!("holds {} which doesn't belong to item 0x{:x}", path.display(), tray_item.id));
+        }
+        has_binary |= extension != constants::SGI_EXT && extension != constants::TRAYITEM_EXT;
+        let filename = path.file_name().and_then(|filename| filename.to_str()).unwrap_or("").to_string();
+        let content = fs::read(path).map_err(|_| format!("holds {} which can't be read", path.display()))?;
+        files.push((filename, content));
+    }
+    if !has_binary {
+        return Err(String::from("has no household, lot or room binary"));
+    }
+    let mut packages: Vec<(PathBuf, Vec<u8>)> = Vec::new();
+    let mods_folder = folder.join(constants::MODS_FOLDER_NAME);
+    for path in utils::list_files_recursive(&mods_folder) {
+        if path.extension().and_then(|extension| extension.to_str()) != Some(constants::PACKAGE_EXT) {
+            continue;
+        }
+        let content = fs::read(&path).map_err(|_| format!("holds {} which can't be read", path.display()))?;
+        packages.push((path.strip_prefix(&mods_folder).unwrap_or(&path).to_path_buf(), content));
+    }
+    return Ok(PackedItem { tray_item, folder: folder.to_path_buf(), files, packages });
+}
+
+/// True when the Tray folder holds exactly the files of the packed item, with the same content.
+fn is_installed(packed_item: &PackedItem, target_paths: &[PathBuf], existing_paths: &[PathBuf]) -> bool {
+    if existing_paths.len() != target_paths.len() || !existing_paths.iter().all(|existing_path| target_paths.contains(existing_path)) {
+        return false;
+    }
+    return target_paths.iter().zip(&packed_item.files).all(|(target_path, (_, content))| {
+        return fs::read(target_path).map(|existing_content| existing_content == *content).unwrap_or(false);
+    });
+}
+
+/// Files of the item renamed for `new_id`, thumbnails keep their offset from the item id. The game reads the id
+/// from the file names and from the trayitem, which is updated as well. Only the low 14 digits of the instance are
+/// the id, the top byte of the original name is kept.
+fn remap_files(packed_item: &PackedItem, new_id: u64) -> Option<Vec<(String, Vec<u8>)>> {
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+    for (filename, content) in &packed_item.files {
+        let path = Path::new(filename);
+        let (_, id) = utils::extract_id_and_type(path)?;
+        let (prefix, instance) = path.file_stem()?.to_str()?.split_once('!')?;
+        let extension = path.extension()?.to_str()?;
+        let new_filename = format!("{}!{}{:014x}.{}", prefix, instance.get(..4)?, new_id + (id - packed_item.tray_item.id), extension);
+        if extension == constants::TRAYITEM_EXT {
+            files.push((new_filename, tray_metadata::set_fields(content, &[(constants::TRAYITEM_ID_FIELD, FieldValue::Number(new_id))])?));
+        } else {
+            files.push((new_filename, content.clone()));
+        }
+    }
+    return Some(files);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::protobuf::{self, WireValue};
+    use crate::tray_metadata::tests::build_trayitem;
+
+    #[test]
+    fn remap_files_keeps_the_top_byte_of_the_instance() {
+        let packed_item = PackedItem {
+            tray_item: TrayItem {
+                path: PathBuf::from("0x00000001!0x00aa12f12d6202c5.trayitem"),
+                name: String::from("Villa Rosa"),
+                creator_name: String::from("simmer42"),
+                creator_id: 0x1234_5678_9abc,
+                id: 0xaa12f12d6202c5,
+                file_type: constants::HOUSEHOLD_TRAYITEM_TYPE,
+                filename: String::from("0x00000001!0x00aa12f12d6202c5.trayitem"),
+                modified: SystemTime::UNIX_EPOCH,
+            },
+            folder: PathBuf::new(),
+            files: vec![
+                (String::from("0x00000001!0x00aa12f12d6202c5.trayitem"), build_trayitem("Villa Rosa", 0x1234_5678_9abc, "simmer42")),
+                (String::from("0x00000000!0x0daa12f12d6202c6.sgi"), vec![1, 2, 3]),
+            ],
+            packages: Vec::new(),
+        };
+        let files = remap_files(&packed_item, 0xaa12f12d620300).unwrap();
+        assert_eq!(files[0].0, "0x00000001!0x00aa12f12d620300.trayitem");
+        assert_eq!(files[1].0, "0x00000000!0x0daa12f12d620301.sgi");
+        assert_eq!(files[1].1, vec![1, 2, 3]);
+        let fields = protobuf::read_fields(&files[0].1[constants::TRAYITEM_HEADER_SIZE..]).unwrap();
+        assert!(matches!(fields[0].value, WireValue::Varint(0xaa12f12d620300)));
+    }
+}
@@ -0,0 +1,201 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use chrono::Local;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::Outcome;
+use crate::{constants, prompts, utils};
+
+/// Written before an operation changes the Tray folder, with a copy of every file as it was so it can be undone.
+#[derive(Deserialize, Serialize)]
+pub struct Journal {
+    pub operation: String,
+    pub created: String,
+    pub files: Vec<JournalFile>,
+}
+
+/// `original` names the saved copy inside the journal folder, no copy means the file didn't exist before.
+#[derive(Deserialize, Serialize)]
+pub struct JournalFile {
+    pub path: PathBuf,
+    pub original: Option<String>,
+    pub modified: u64,
+}
+
+pub fn get_journal_folder() -> Option<PathBuf> {
+    let project_dirs = ProjectDirs::from("", "", constants::APPLICATION_NAME)?;
+    return Some(project_dirs.data_dir().join(constants::JOURNAL_FOLDERNAME));
+}
+
+/// Saves the current state of every path the operation is about to write or delete.
+/// The operation must not start when this fails, it couldn't be undone.
+pub fn record(operation: &str, paths: &[&Path]) -> Result<(), String> {
+    let journal_folder = get_journal_folder().ok_or(String::from("Couldn't find the data directory to store the undo journal"))?;
+    let name = utils::get_new_timestamp_name(&journal_folder, "");
+    let entry_folder = journal_folder.join(&name);
+    fs::create_dir_all(&entry_folder).map_err(|_| format!("Couldn't create the undo journal {}", entry_folder.display()))?;
+    let mut journal = Journal {
+        operation: String::from(operation),
+        created: Local::now().to_rfc3339(),
+        files: Vec::new(),
+    };
+    for (index, path) in paths.iter().enumerate() {
+        // Undo may run from another folder, so relative paths are resolved now.
+        let mut journal_file = JournalFile {
+            path: std::path::absolute(path).unwrap_or(path.to_path_buf()),
+            original: None,
+            modified: 0,
+        };
+        if path.exists() {
+            let original = index.to_string();
+            if fs::copy(path, entry_folder.join(&original)).is_err() {
+                let _ = fs::remove_dir_all(&entry_folder);
+                return Err(format!("Couldn't save {} in the undo journal", path.display()));
+            }
+            let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).unwrap_or(UNIX_EPOCH);
+            journal_file.modified = modified.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+            journal_file.original = Some(original);
+        }
+        journal.files.push(journal_file);
+    }
+    let written = toml::to_string(&journal).map_err(|_| ())
+        .and_then(|content| utils::write_file_atomically(&entry_folder.join(constants::JOURNAL_FILENAME), content.as_bytes()).map_err(|_| ()));
+    if written.is_err() {
+        let _ = fs::remove_dir_all(&entry_folder);
+        return Err(String::from("Couldn't write the undo journal"));
+    }
+    utils::print_debug(format!("Undo journal for {} files saved to {}", journal.files.len(), entry_folder.display()));
+    prune(&journal_folder);
+    return Ok(());
+}
+
+pub fn list() -> Outcome {
+    let journal_folder = match get_journal_folder() {
+        Some(journal_folder) => journal_folder,
+        None => {
+            utils::print_error(String::from("Couldn't find the data directory holding the undo journal!"));
+            return Outcome::Failure;
+        }
+    };
+    let names = get_journal_names(&journal_folder);
+    if names.is_empty() {
+        utils::print_info(String::from("There is nothing to undo."));
+        return Outcome::NothingToDo;
+    }
+    for name in names.iter().rev() {
+        match load_journal(&journal_folder, name) {
+            Some(journal) => {
+                utils::print_info(format!("{}  {} ({} files)", name, journal.operation, journal.files.len()));
+            },
+            None => {
+                utils::print_warning(format!("{}  unreadable journal", name));
+            }
+        }
+    }
+    return Outcome::Success;
+}
+
+/// Puts back every file of the last operation as it was before, then forgets that operation.
+pub fn undo() -> Outcome {
+    let journal_folder = match get_journal_folder() {
+        Some(journal_folder) => journal_folder,
+        None => {
+            utils::print_error(String::from("Couldn't find the data directory holding the undo journal!"));
+            return Outcome::Failure;
+        }
+    };
+    let name = match get_journal_names(&journal_folder).pop() {
+        Some(name) => name,
+        None => {
+            utils::print_info(String::from("There is nothing to undo."));
+            return Outcome::NothingToDo;
+        }
+    };
+    let entry_folder = journal_folder.join(&name);
+    let journal = match load_journal(&journal_folder, &name) {
+        Some(journal) => journal,
+        None => {
+            utils::print_error(format!("The undo journal {} can't be read!", name));
+            return Outcome::Failure;
+        }
+    };
+    let mut restored_files: Vec<(&Path, Vec<u8>)> = Vec::new();
+    let mut deleted_files: Vec<&Path> = Vec::new();
+    for journal_file in &journal.files {
+        match &journal_file.original {
+            Some(original) => {
+                match fs::read(entry_folder.join(original)) {
+                    Ok(content) => {
+                        restored_files.push((&journal_file.path, content));
+                    },
+                    Err(_) => {
+                        utils::print_error(format!("The saved copy of {} is missing, the operation can't be undone!", journal_file.path.display()));
+                        return Outcome::Failure;
+                    }
+                }
+            },
+            None => {
+                deleted_files.push(&journal_file.path);
+            }
+        }
+    }
+    utils::print_info(format!("Last operation: {} ({})", journal.operation, journal.created));
+    if !prompts::confirm(format!("{} files will be put back and {} deleted. Do you want to continue?", restored_files.len(), deleted_files.len())) {
+        return Outcome::Failure;
+    }
+    let staged_files: Vec<(&Path, &[u8])> = restored_files.iter().map(|(path, content)| (*path, content.as_slice())).collect();
+    if let Err(e) = utils::write_files_atomically(&staged_files) {
+        utils::print_error(format!("{}, nothing was undone!", e));
+        return Outcome::Failure;
+    }
+    let mut failed_files = 0;
+    for journal_file in &journal.files {
+        if journal_file.original.is_some() {
+            let modified = UNIX_EPOCH + Duration::from_secs(journal_file.modified);
+            let _ = File::options().write(true).open(&journal_file.path).and_then(|file| file.set_modified(modified));
+        }
+    }
+    for path in deleted_files {
+        if path.exists() && fs::remove_file(path).is_err() {
+            utils::print_error(format!("Couldn't delete {}!", path.display()));
+            failed_files += 1;
+        }
+    }
+    let _ = fs::remove_dir_all(&entry_folder);
+    if failed_files == 0 {
+        utils::print_success(format!("Undid {}.", journal.operation));
+        return Outcome::Success;
+    }
+    utils::print_warning(format!("Undid {} with {} errors.", journal.operation, failed_files));
+    return Outcome::PartialFailure;
+}
+
+fn load_journal(journal_folder: &Path, name: &str) -> Option<Journal> {
+    let content = fs::read_to_string(journal_folder.join(name).join(constants::JOURNAL_FILENAME)).ok()?;
+    return toml::from_str(&content).ok();
+}
+
+/// Names are timestamps, so they sort from oldest to newest. Folders without a journal file are unfinished records.
+fn get_journal_names(journal_folder: &Path) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    if let Ok(items) = fs::read_dir(journal_folder) {
+        for item in items.flatten() {
+            if item.path().join(constants::JOURNAL_FILENAME).is_file() {
+                names.push(item.file_name().to_string_lossy().to_string());
+            }
+        }
+    }
+    names.sort();
+    return names;
+}
+
+fn prune(journal_folder: &Path) {
+    let names = get_journal_names(journal_folder);
+    let removed = names.len().saturating_sub(constants::JOURNAL_KEEP);
+    for name in &names[..removed] {
+        let _ = fs::remove_dir_all(journal_folder.join(name));
+    }
+}
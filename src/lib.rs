@@ -0,0 +1,30 @@
+#![allow(clippy::needless_return)]
+
+pub mod utils;
+pub mod logging;
+pub mod tray_files;
+pub mod tray_location;
+pub mod tray_metadata;
+pub mod protobuf;
+pub mod naming;
+pub mod filters;
+pub mod prompts;
+pub mod progress;
+pub mod packing;
+pub mod bundle;
+pub mod anonymize;
+pub mod signing;
+pub mod backup;
+pub mod diff;
+pub mod edit;
+pub mod journal;
+pub mod install;
+pub mod duplicates;
+pub mod stats;
+pub mod inspect;
+pub mod watch;
+pub mod dbpf;
+pub mod custom_content;
+pub mod cli;
+pub mod config;
+pub mod constants;
@@ -0,0 +1,125 @@
+use std::cell::RefCell;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local};
+use colored::Colorize;
+
+use crate::progress;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warning,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// One message, `label` is what the console shows in front of it.
+pub struct Record {
+    level: Level,
+    label: &'static str,
+    message: String,
+    time: DateTime<Local>,
+}
+
+static CONSOLE_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+thread_local! {
+    /// Records of a task of `utils::parallel_map`, held back until the tasks before it are done.
+    static CAPTURED_RECORDS: RefCell<Option<Vec<Record>>> = const { RefCell::new(None) };
+}
+
+/// `verbosity` is the number of -v minus the number of -q. The log file always gets debug messages,
+/// a bug report is of little use without them.
+pub fn init(verbosity: i8, log_file: Option<&Path>) -> Result<(), String> {
+    let level = match verbosity {
+        ..=-2 => Level::Error,
+        -1 => Level::Warning,
+        0 => Level::Info,
+        1 => Level::Debug,
+        _ => Level::Trace
+    };
+    CONSOLE_LEVEL.store(level as u8, Ordering::Relaxed);
+    if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        colored::control::set_override(false);
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+    if let Some(log_file) = log_file {
+        let mut file = OpenOptions::new().create(true).append(true).open(log_file)
+            .map_err(|_| format!("Couldn't open the log file {}", log_file.display()))?;
+        let arguments: Vec<String> = env::args().collect();
+        let _ = writeln!(file, "{} ----- {} {} started: {}", Local::now().to_rfc3339(), env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), arguments.join(" "));
+        if let Ok(mut log_file) = LOG_FILE.lock() {
+            *log_file = Some(file);
+        }
+    }
+    return Ok(());
+}
+
+/// Lets callers skip building messages nobody will see.
+pub fn is_enabled(level: Level) -> bool {
+    if level as u8 <= CONSOLE_LEVEL.load(Ordering::Relaxed) {
+        return true;
+    }
+    return level <= Level::Debug && LOG_FILE.lock().map(|log_file| log_file.is_some()).unwrap_or(false);
+}
+
+pub fn log(level: Level, label: &'static str, message: String) {
+    if !is_enabled(level) {
+        return;
+    }
+    let record = Record { level, label, message, time: Local::now() };
+    let captured = CAPTURED_RECORDS.with(|captured| {
+        match captured.borrow_mut().as_mut() {
+            Some(records) => {
+                records.push(record);
+                return None;
+            },
+            None => {
+                return Some(record);
+            }
+        }
+    });
+    if let Some(record) = captured {
+        write_record(&record);
+    }
+}
+
+pub fn start_capture() {
+    CAPTURED_RECORDS.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+}
+
+pub fn finish_capture() -> Vec<Record> {
+    return CAPTURED_RECORDS.with(|captured| captured.borrow_mut().take()).unwrap_or_default();
+}
+
+pub fn write_record(record: &Record) {
+    let on_console = record.level as u8 <= CONSOLE_LEVEL.load(Ordering::Relaxed);
+    if on_console {
+        let label = format!("[{}]", record.label);
+        let label = match record.label {
+            "ERROR" => label.red(),
+            "WARNING" => label.yellow(),
+            "SUCCESS" => label.bright_green(),
+            "INFO" => label.bright_blue(),
+            "DEBUG" => label.bright_magenta(),
+            _ => label.dimmed()
+        };
+        progress::print_line(&format!("{} {}", label, record.message));
+    }
+    if on_console || record.level <= Level::Debug {
+        if let Ok(mut log_file) = LOG_FILE.lock() {
+            if let Some(file) = log_file.as_mut() {
+                let _ = writeln!(file, "{} {:<7} {}", record.time.to_rfc3339(), record.label, record.message);
+            }
+        }
+    }
+}
@@ -1,92 +1,211 @@
-use std::fs;
+#![allow(clippy::needless_return)]
 
-mod utils;
-mod tray_files;
-mod constants;
+use std::process;
+
+use clap::Parser;
+
+use sims_4_gallery_packer::{backup, bundle, cli, config, constants, custom_content, diff, duplicates, edit, filters, inspect, install, journal, logging, naming, packing, prompts, signing, stats, utils, watch};
+use sims_4_gallery_packer::utils::Outcome;
 
 fn main() {
-    if let Some(tray_folder) = utils::get_tray_folder() {
-        if let Some(output_folder) = utils::prepare_output_folder() {
-            let tray_content = utils::read_tray_files(tray_folder);
-            for tray_item in tray_content.trayitem_files {
-                if let Some(item_type_folder) = utils::preapare_output_folder_for_type(&output_folder, tray_item.file_type) {
-                    let hex_id = format!("0x{:x}", tray_item.id);
-                    let output_folder_name = format!("{} ({})", tray_item.name, hex_id);
-                    let tray_item_folder = item_type_folder.join(output_folder_name);
-                    match fs::create_dir(&tray_item_folder) {
-                        Ok(_) => {
-                            let tray_item_file_path = tray_item_folder.join(&tray_item.filename);
-                            match fs::copy(&tray_item.path, tray_item_file_path) {
-                                Ok(_) => {
-                                    utils::print_success(format!("[{}] {} copied to gallery item folder.", tray_item.name, tray_item.filename));
-                                    for unknown_item in &tray_content.unknown_files {
-                                        if unknown_item.id == tray_item.id {
-                                            let unknown_item_path = tray_item_folder.join(&unknown_item.filename);
-                                            match fs::copy(&unknown_item.path, unknown_item_path) {
-                                                Ok(_) => {
-                                                    utils::print_success(format!("[{}] {} copied to gallery item folder.", tray_item.name, unknown_item.filename));
-                                                },
-                                                Err(_) => {
-                                                    utils::print_error(format!("[{}] Couldn't copy {} to gallery item folder!", tray_item.name, unknown_item.filename));
-                                                    utils::print_error(format!("[{}] The resulting gallery item will be corrupted!", tray_item.name));
-                                                }
-                                            }
-                                        }
-                                    }
-                                    let mut check_sgi = true;
-                                    let mut expected_id = tray_item.id + 1;
-                                    while check_sgi {
-                                        for sgi_file in &tray_content.sgi_files {
-                                            if sgi_file.id == expected_id {
-                                                let sgi_target_path = tray_item_folder.join(&sgi_file.filename);
-                                                match fs::copy(&sgi_file.path, sgi_target_path) {
-                                                    Ok(_) => {
-                                                        utils::print_success(format!("[{}] {} copied to gallery item folder.", tray_item.name, sgi_file.filename));
-                                                    },
-                                                    Err(_) => {
-                                                        utils::print_error(format!("[{}] Couldn't copy {} to gallery item folder!", tray_item.name, sgi_file.filename));
-                                                        utils::print_warning(format!("[{}] The resulting gallery item will be corrupted!", tray_item.name));
-                                                    }
-                                                }
-                                                expected_id += 1;
-                                            } else {
-                                                check_sgi = false;
-                                            }
-                                        }
-                                    }
-                                },
-                                Err(_) => {
-                                    utils::print_error(format!("[{}] Couldn't copy {} to gallery item folder!", tray_item.name, tray_item.filename));
-                                    utils::print_warning(format!("[{}] The whole gallery item will be skipped!", tray_item.name));
-                                }
-                            }
-                        },
-                        Err(_) => {
-                            utils::print_error(format!("[{}] Couldn't create output folder, the whole item will be skipped!", tray_item.name));
-                        }
+    let mut args = cli::Cli::parse();
+    let started = config::load(args.config.as_deref())
+        .and_then(|config| config::apply(config, &mut args))
+        .and_then(|_| logging::init(args.verbose as i8 - args.quiet as i8, args.log_file.as_deref()));
+    let outcome = match started {
+        Ok(_) => {
+            prompts::set_assume_yes(args.yes);
+            run(&args)
+        },
+        Err(e) => {
+            utils::print_error(e);
+            Outcome::Failure
+        }
+    };
+    if !args.no_pause {
+        prompts::wait_for_key(String::from("Program completed! Press any key to continue..."));
+    }
+    process::exit(outcome.exit_code());
+}
+
+fn run(args: &cli::Cli) -> Outcome {
+    match &args.command {
+        None => {
+            return pack(args, &args.pack);
+        },
+        Some(cli::Command::Pack(pack_args)) => {
+            return pack(args, pack_args);
+        },
+        Some(cli::Command::Backup(backup_args)) => {
+            if let Some(tray_folder) = utils::get_tray_folder(args.tray.as_deref()) {
+                if let Some(store) = backup::get_backup_store(backup_args.store.store.as_deref()) {
+                    return backup::backup(&tray_folder, &store, backup_args.keep);
+                }
+            }
+            return Outcome::Failure;
+        },
+        Some(cli::Command::Snapshots(store_args)) => {
+            if let Some(store) = backup::get_backup_store(store_args.store.as_deref()) {
+                return backup::list_snapshots(&store);
+            }
+            return Outcome::Failure;
+        },
+        Some(cli::Command::Restore(restore_args)) => {
+            if let Some(tray_folder) = utils::get_tray_folder(args.tray.as_deref()) {
+                if let Some(store) = backup::get_backup_store(restore_args.store.store.as_deref()) {
+                    return backup::restore(&tray_folder, &store, &restore_args.snapshot, restore_args.clean);
+                }
+            }
+            return Outcome::Failure;
+        },
+        Some(cli::Command::Prune(prune_args)) => {
+            match prune_args.keep {
+                Some(keep) => {
+                    if let Some(store) = backup::get_backup_store(prune_args.store.store.as_deref()) {
+                        return backup::prune(&store, keep);
                     }
-                } else {
-                    let tray_item_type_name: String;
-                    match tray_item.file_type {
-                        constants::HOUSEHOLD_TRAYITEM_TYPE => {
-                            tray_item_type_name = String::from(constants::HOUSEHOLD_FOLDERNAME);
-                        },
-                        constants::PLOT_TRAYITEM_TYPE => {
-                            tray_item_type_name = String::from(constants::PLOT_FOLDERNAME);
-                        },
-                        constants::ROOM_TRAYITEM_TYPE => {
-                            tray_item_type_name = String::from(constants::ROOM_FOLDERNAME);
-                        },
-                        _ => {
-                            tray_item_type_name = String::from("unknown");
-                        }
+                    return Outcome::Failure;
+                },
+                None => {
+                    utils::print_error(String::from("Set how many snapshots to keep with --keep or in the configuration file!"));
+                    return Outcome::Failure;
+                }
+            }
+        },
+        Some(cli::Command::Diff(diff_args)) => {
+            return diff::diff(&diff_args.old, &diff_args.new, diff_args.store.store.as_deref());
+        },
+        Some(cli::Command::Deps(deps_args)) => {
+            match filters::TrayItemFilter::new(&deps_args.filters) {
+                Ok(filter) => {
+                    if let Some(tray_folder) = utils::get_tray_folder(args.tray.as_deref()) {
+                        return custom_content::list_dependencies(&tray_folder, args.mods.as_deref(), &filter);
                     }
-                    utils::print_error(format!("Couldn't create output folder for gallery item type {}!", tray_item_type_name));
-                    utils::print_warning(format!("All {} gallery items will be skipped!", tray_item_type_name));
+                    return Outcome::Failure;
+                },
+                Err(e) => {
+                    utils::print_error(e);
+                    return Outcome::Failure;
+                }
+            }
+        },
+        Some(cli::Command::Unpack(unpack_args)) => {
+            if let Some(tray_folder) = utils::get_tray_folder(args.tray.as_deref()) {
+                return bundle::unpack(&unpack_args.bundle, &tray_folder, args.mods.as_deref(), &unpack_args.trusted_key, unpack_args.require_signature);
+            }
+            return Outcome::Failure;
+        },
+        Some(cli::Command::Keygen(keygen_args)) => {
+            return signing::generate_key(keygen_args.force);
+        },
+        Some(cli::Command::Edit(edit_args)) => {
+            return edit(args, edit_args);
+        },
+        Some(cli::Command::Undo(undo_args)) => {
+            if undo_args.list {
+                return journal::list();
+            }
+            return journal::undo();
+        },
+        Some(cli::Command::Watch(watch_args)) => {
+            match prepare_pack(&watch_args.pack) {
+                Ok((name_template, filter)) => {
+                    return watch::watch(args.tray.as_deref(), args.mods.as_deref(), watch_args, &name_template, &filter);
+                },
+                Err(e) => {
+                    utils::print_error(e);
+                    return Outcome::Failure;
                 }
             }
+        },
+        Some(cli::Command::RestoreOutput(restore_output_args)) => {
+            match filters::TrayItemFilter::new(&restore_output_args.filters) {
+                Ok(filter) => {
+                    let output_folder = match &restore_output_args.path {
+                        Some(path) => Some(path.clone()),
+                        None => utils::get_output_folder(None)
+                    };
+                    if let (Some(output_folder), Some(tray_folder)) = (output_folder, utils::get_tray_folder(args.tray.as_deref())) {
+                        return install::restore_output(&output_folder, &tray_folder, args.mods.as_deref(), &filter, restore_output_args.on_conflict);
+                    }
+                    return Outcome::Failure;
+                },
+                Err(e) => {
+                    utils::print_error(e);
+                    return Outcome::Failure;
+                }
+            }
+        },
+        Some(cli::Command::Duplicates(duplicates_args)) => {
+            match filters::TrayItemFilter::new(&duplicates_args.filters) {
+                Ok(filter) => {
+                    if let Some(tray_folder) = utils::get_tray_folder(args.tray.as_deref()) {
+                        return duplicates::duplicates(&tray_folder, &filter, duplicates_args.list);
+                    }
+                    return Outcome::Failure;
+                },
+                Err(e) => {
+                    utils::print_error(e);
+                    return Outcome::Failure;
+                }
+            }
+        },
+        Some(cli::Command::Stats(stats_args)) => {
+            match filters::TrayItemFilter::new(&stats_args.filters) {
+                Ok(filter) => {
+                    if let Some(tray_folder) = utils::get_tray_folder(args.tray.as_deref()) {
+                        return stats::stats(&tray_folder, &filter, stats_args.top);
+                    }
+                    return Outcome::Failure;
+                },
+                Err(e) => {
+                    utils::print_error(e);
+                    return Outcome::Failure;
+                }
+            }
+        },
+        Some(cli::Command::Inspect(inspect_args)) => {
+            return inspect::inspect(&inspect_args.item, args.tray.as_deref());
+        },
+        Some(cli::Command::Dump(dump_args)) => {
+            return inspect::dump(&dump_args.path, dump_args.offset, dump_args.length, dump_args.depth);
         }
     }
-    utils::print_info(String::from("Program completed! Press any key to continue..."));
-    press_btn_continue::wait("").unwrap();
-}
\ No newline at end of file
+}
+
+fn edit(args: &cli::Cli, edit_args: &cli::EditArgs) -> Outcome {
+    match filters::TrayItemFilter::new(&edit_args.filters).and_then(|filter| Ok((filter, edit::MetadataChanges::new(edit_args)?))) {
+        Ok((filter, changes)) => {
+            let folder = match &edit_args.path {
+                Some(path) => Some(path.clone()),
+                None => utils::get_tray_folder(args.tray.as_deref())
+            };
+            if let Some(folder) = folder {
+                return edit::edit(&folder, &filter, &changes, edit_args.dry_run);
+            }
+            return Outcome::Failure;
+        },
+        Err(e) => {
+            utils::print_error(e);
+            return Outcome::Failure;
+        }
+    }
+}
+
+fn pack(args: &cli::Cli, pack_args: &cli::PackArgs) -> Outcome {
+    match prepare_pack(pack_args) {
+        Ok((name_template, filter)) => {
+            return packing::pack(args.tray.as_deref(), args.mods.as_deref(), pack_args, &name_template, &filter);
+        },
+        Err(e) => {
+            utils::print_error(e);
+            return Outcome::Failure;
+        }
+    }
+}
+
+fn prepare_pack(pack_args: &cli::PackArgs) -> Result<(naming::NameTemplate, filters::TrayItemFilter), String> {
+    let name_template = naming::NameTemplate::parse(pack_args.name_template.as_deref().unwrap_or(constants::DEFAULT_NAME_TEMPLATE))?;
+    let filter = filters::TrayItemFilter::new(&pack_args.filters)?;
+    return Ok((name_template, filter));
+}
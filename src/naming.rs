@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local};
+
+use crate::tray_files::TrayItem;
+use crate::utils;
+
+const FIELDS: [&str; 6] = ["type", "name", "creator", "creator_id", "id", "date"];
+
+enum Segment {
+    Literal(String),
+    Field(String),
+}
+
+pub struct NameTemplate {
+    components: Vec<Vec<Segment>>,
+}
+
+impl NameTemplate {
+    pub fn parse(template: &str) -> Result<NameTemplate, String> {
+        let mut components: Vec<Vec<Segment>> = Vec::new();
+        for component in template.split(['/', '\\']) {
+            if component.is_empty() || component == "." || component == ".." {
+                return Err(format!("Invalid path component \"{}\" in naming template \"{}\"", component, template));
+            }
+            let mut segments: Vec<Segment> = Vec::new();
+            let mut rest = component;
+            while !rest.is_empty() {
+                match rest.find(['{', '}']) {
+                    Some(start) if rest[start..].starts_with('{') => {
+                        if start > 0 {
+                            segments.push(Segment::Literal(String::from(&rest[..start])));
+                        }
+                        match rest[start..].find('}') {
+                            Some(end) => {
+                                let field = &rest[start + 1..start + end];
+                                if !FIELDS.contains(&field) {
+                                    return Err(format!("Unknown field {{{}}} in naming template, available fields are {{{}}}", field, FIELDS.join("}, {")));
+                                }
+                                segments.push(Segment::Field(String::from(field)));
+                                rest = &rest[start + end + 1..];
+                            },
+                            None => {
+                                return Err(format!("Unclosed \"{{\" in naming template \"{}\"", template));
+                            }
+                        }
+                    },
+                    Some(_) => {
+                        return Err(format!("Unexpected \"}}\" in naming template \"{}\"", template));
+                    },
+                    None => {
+                        segments.push(Segment::Literal(String::from(rest)));
+                        rest = "";
+                    }
+                }
+            }
+            components.push(segments);
+        }
+        let has_unique_field = components.iter().flatten().any(|segment| match segment {
+            Segment::Field(field) => field == "id" || field == "name",
+            Segment::Literal(_) => false,
+        });
+        if !has_unique_field {
+            return Err(format!("Naming template \"{}\" must contain {{id}} or {{name}}", template));
+        }
+        return Ok(NameTemplate { components });
+    }
+
+    pub fn render(&self, tray_item: &TrayItem) -> PathBuf {
+        let mut path = PathBuf::new();
+        for segments in &self.components {
+            let mut component = String::new();
+            for segment in segments {
+                match segment {
+                    Segment::Literal(literal) => {
+                        component.push_str(literal);
+                    },
+                    Segment::Field(field) => {
+                        component.push_str(&sanitize(&field_value(field, tray_item)));
+                    }
+                }
+            }
+            let component = component.trim().trim_end_matches('.');
+            if component.is_empty() {
+                path.push("_");
+            } else {
+                path.push(component);
+            }
+        }
+        return path;
+    }
+}
+
+fn field_value(field: &str, tray_item: &TrayItem) -> String {
+    match field {
+        "type" => {
+            return String::from(utils::get_type_folder_name(tray_item.file_type).unwrap_or("unknown"));
+        },
+        "name" => {
+            return tray_item.name.clone();
+        },
+        "creator" => {
+            return tray_item.creator_name.clone();
+        },
+        "creator_id" => {
+            return format!("0x{:x}", tray_item.creator_id);
+        },
+        "id" => {
+            return format!("0x{:x}", tray_item.id);
+        },
+        "date" => {
+            let modified: DateTime<Local> = DateTime::from(tray_item.modified);
+            return modified.format("%Y-%m-%d").to_string();
+        },
+        _ => {
+            return String::new();
+        }
+    }
+}
+
+/// Replaces the characters file systems refuse in a name.
+pub fn sanitize(value: &str) -> String {
+    let mut result = String::new();
+    for character in value.chars() {
+        match character {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => {
+                result.push('_');
+            },
+            _ if character.is_control() => {
+                result.push('_');
+            },
+            _ => {
+                result.push(character);
+            }
+        }
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::constants;
+
+    fn get_tray_item(name: &str, creator_name: &str) -> TrayItem {
+        return TrayItem {
+            path: PathBuf::from("0x00000001!0x00aa12f12d6202c5.trayitem"),
+            name: String::from(name),
+            creator_name: String::from(creator_name),
+            creator_id: 0x1234_5678_9abc,
+            id: 0xaa12f12d6202c5,
+            file_type: constants::HOUSEHOLD_TRAYITEM_TYPE,
+            filename: String::from("0x00000001!0x00aa12f12d6202c5.trayitem"),
+            // Noon UTC, the same day in nearly every local time zone.
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(1_715_947_200),
+        };
+    }
+
+    #[test]
+    fn parse_refuses_invalid_templates() {
+        for (template, error) in [
+            ("{name}//{id}", "Invalid path component"),
+            ("../{id}", "Invalid path component"),
+            ("{type}/./{id}", "Invalid path component"),
+            ("{type}/", "Invalid path component"),
+            ("{title} {id}", "Unknown field {title}"),
+            ("{name", "Unclosed"),
+            ("name} {id}", "Unexpected"),
+            ("{type}/{creator} {date}", "must contain {id} or {name}"),
+        ] {
+            match NameTemplate::parse(template) {
+                Ok(_) => panic!("{} was accepted", template),
+                Err(e) => assert!(e.contains(error), "{}: {}", template, e),
+            }
+        }
+        assert!(NameTemplate::parse(constants::DEFAULT_NAME_TEMPLATE).is_ok());
+        assert!(NameTemplate::parse("{name}").is_ok());
+        assert!(NameTemplate::parse("{creator}\\{id}").is_ok());
+    }
+
+    #[test]
+    fn render_fills_every_field() {
+        let tray_item = get_tray_item("Villa Rosa", "simmer42");
+        let template = NameTemplate::parse("{type}/{creator} ({creator_id})/{date} {name} ({id})").unwrap();
+        let expected_date = DateTime::<Local>::from(tray_item.modified).format("%Y-%m-%d").to_string();
+        let expected_path = Path::new(constants::HOUSEHOLD_FOLDERNAME).join("simmer42 (0x123456789abc)").join(format!("{} Villa Rosa (0xaa12f12d6202c5)", expected_date));
+        assert_eq!(template.render(&tray_item), expected_path);
+    }
+
+    #[test]
+    fn render_keeps_names_inside_their_folder() {
+        let template = NameTemplate::parse("{creator}/{name}").unwrap();
+        assert_eq!(template.render(&get_tray_item("../Villa: Rosa?", "a/b")), Path::new("a_b").join(".._Villa_ Rosa_"));
+        assert_eq!(template.render(&get_tray_item("..", " ")), Path::new("_").join("_"));
+        assert_eq!(template.render(&get_tray_item("Villa Rosa. ", "simmer\t42")), Path::new("simmer_42").join("Villa Rosa"));
+    }
+}
@@ -0,0 +1,451 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use ed25519_dalek::SigningKey;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::anonymize::Anonymizer;
+use crate::bundle::{self, BundleItem};
+use crate::cli::{OutputFormat, PackArgs};
+use crate::custom_content::{self, ModsIndex};
+use crate::filters::TrayItemFilter;
+use crate::naming::NameTemplate;
+use crate::progress::PackProgress;
+use crate::tray_files::{TrayFolderContent, TrayItem, TrayItemFile};
+use crate::utils::Outcome;
+use crate::{constants, prompts, signing, utils};
+
+/// Options of a pack run that don't change from one item to the next, prepared once.
+pub struct Packer<'a> {
+    pub format: OutputFormat,
+    name_template: &'a NameTemplate,
+    signing_key: Option<SigningKey>,
+    anonymizer: Option<Anonymizer>,
+    mods_index: Option<ModsIndex>,
+}
+
+impl<'a> Packer<'a> {
+    pub fn new(tray_folder: &Path, mods_override: Option<&Path>, pack_args: &PackArgs, name_template: &'a NameTemplate) -> Option<Packer<'a>> {
+        let format = pack_args.format.unwrap_or(OutputFormat::S4pack);
+        let mut signing_key: Option<SigningKey> = None;
+        if pack_args.sign {
+            if !matches!(format, OutputFormat::S4pack) {
+                utils::print_error(String::from("Only .s4pack bundles can be signed, use --format s4pack!"));
+                return None;
+            }
+            match signing::load_signing_key() {
+                Ok(key) => {
+                    signing_key = Some(key);
+                },
+                Err(e) => {
+                    utils::print_error(e);
+                    return None;
+                }
+            }
+        }
+        let mut anonymizer: Option<Anonymizer> = None;
+        if pack_args.anonymize {
+            anonymizer = Some(Anonymizer {
+                creator_name: pack_args.anonymous_name.clone().unwrap_or(String::from(constants::ANONYMOUS_CREATOR_NAME)),
+                creator_id: pack_args.anonymous_id.unwrap_or(constants::ANONYMOUS_CREATOR_ID),
+            });
+        }
+        let mut mods_index: Option<ModsIndex> = None;
+        if pack_args.include_cc {
+            match custom_content::get_mods_folder(tray_folder, mods_override) {
+                Some(mods_folder) => {
+                    mods_index = Some(ModsIndex::new(&mods_folder));
+                },
+                None => {
+                    utils::print_warning(String::from("Custom content won't be included in the packs!"));
+                }
+            }
+        }
+        return Some(Packer { format, name_template, signing_key, anonymizer, mods_index });
+    }
+
+    /// The creator may be part of the name template, it must not leak through the output names either.
+    pub fn get_relative_path(&self, tray_item: &TrayItem) -> PathBuf {
+        match &self.anonymizer {
+            Some(anonymizer) => {
+                return self.name_template.render(&anonymizer.anonymize_item(tray_item));
+            },
+            None => {
+                return self.name_template.render(tray_item);
+            }
+        }
+    }
+
+    /// Path of the folder, archive or bundle the item is packed to when packed on its own.
+    pub fn get_output_path(&self, output_folder: &Path, relative_path: &Path) -> PathBuf {
+        match self.format {
+            OutputFormat::Folder => {
+                return output_folder.join(relative_path);
+            },
+            OutputFormat::Zip => {
+                return with_added_extension(&output_folder.join(relative_path), "zip");
+            },
+            OutputFormat::S4pack => {
+                return with_added_extension(&output_folder.join(relative_path), constants::BUNDLE_EXT);
+            }
+        }
+    }
+
+    fn get_custom_content_files(&self, tray_item: &TrayItem, companion_files: &[&TrayItemFile]) -> Vec<(&Path, PathBuf)> {
+        let mut custom_content_files: Vec<(&Path, PathBuf)> = Vec::new();
+        if let Some(mods_index) = &self.mods_index {
+            for package in mods_index.find_dependencies(tray_item, companion_files) {
+                let relative_path = package.strip_prefix(&mods_index.mods_folder).unwrap_or(package);
+                custom_content_files.push((package, Path::new(constants::MODS_FOLDER_NAME).join(relative_path)));
+            }
+        }
+        return custom_content_files;
+    }
+
+    /// Packs every item into the output folder, all of them in one bundle for the s4pack format.
+    pub fn pack_items(&self, output_folder: &Path, tray_content: &TrayFolderContent, tray_items: &[&TrayItem], progress: &PackProgress) {
+        let mut jobs: Vec<(&TrayItem, PathBuf)> = Vec::new();
+        let mut relative_paths: HashSet<PathBuf> = HashSet::new();
+        for tray_item in tray_items {
+            let tray_item_relative_path = self.get_relative_path(tray_item);
+            // Items are packed in parallel, two of them must never race for the same output. Names only differing
+            // by case are the same folder on Windows and macOS.
+            let folded_relative_path = PathBuf::from(tray_item_relative_path.to_string_lossy().to_lowercase());
+            if !matches!(self.format, OutputFormat::S4pack) && !relative_paths.insert(folded_relative_path) {
+                utils::print_error(format!("{} is already used by another gallery item, check that the naming template produces unique names!", tray_item_relative_path.display()));
+                utils::print_warning(format!("[{}] The whole gallery item will be skipped!", tray_item.name));
+                progress.finish_item(tray_item, false);
+                continue;
+            }
+            jobs.push((tray_item, tray_item_relative_path));
+        }
+        let packed_items = utils::parallel_map(&jobs, |(tray_item, tray_item_relative_path)| {
+            utils::print_debug(format!("[{}] Packing to {}", tray_item.name, tray_item_relative_path.display()));
+            let companion_files = tray_content.companion_files(tray_item);
+            let custom_content_files = self.get_custom_content_files(tray_item, &companion_files);
+            match self.format {
+                OutputFormat::Folder => {
+                    let packed = pack_item_to_folder(output_folder, tray_item_relative_path, tray_item, &companion_files, &custom_content_files, self.anonymizer.as_ref(), progress);
+                    progress.finish_item(tray_item, packed);
+                    return None;
+                },
+                OutputFormat::Zip => {
+                    let packed = pack_item_to_zip(output_folder, tray_item_relative_path, tray_item, &companion_files, &custom_content_files, self.anonymizer.as_ref(), progress);
+                    progress.finish_item(tray_item, packed);
+                    return None;
+                },
+                OutputFormat::S4pack => {
+                    return Some(BundleItem {
+                        tray_item,
+                        relative_path: tray_item_relative_path.clone(),
+                        companion_files,
+                        custom_content_files,
+                    });
+                }
+            }
+        });
+        let bundle_items: Vec<BundleItem> = packed_items.into_iter().flatten().collect();
+        // Folders created for archives that couldn't be written are removed once no other item can still use them.
+        if matches!(self.format, OutputFormat::Zip) {
+            for (_, tray_item_relative_path) in &jobs {
+                if let Some(parent) = output_folder.join(tray_item_relative_path).parent() {
+                    let _ = fs::remove_dir(parent);
+                }
+            }
+        }
+        if !bundle_items.is_empty() {
+            let bundle_name = format!("{}.{}", constants::BUNDLE_DEFAULT_NAME, constants::BUNDLE_EXT);
+            if !bundle::write_bundle(&output_folder.join(bundle_name), &bundle_items, self.anonymizer.as_ref(), self.signing_key.as_ref(), progress) {
+                progress.skip_packed_items();
+            }
+        }
+        let _ = fs::remove_dir(output_folder.join(constants::STAGING_FOLDERNAME));
+    }
+
+    /// Packs one item to `output_path` on its own, bundles included, replacing what was there only once the new pack
+    /// is complete. Used to update an item inside an output folder that is kept between runs.
+    pub fn pack_item_update(&self, output_folder: &Path, tray_content: &TrayFolderContent, tray_item: &TrayItem, output_path: &Path) -> bool {
+        let staging_output_folder = output_folder.join(constants::STAGING_FOLDERNAME).join(format!("update-{:016x}", tray_item.id));
+        let _ = fs::remove_dir_all(&staging_output_folder);
+        let relative_path = self.get_relative_path(tray_item);
+        let staged_path = self.get_output_path(&staging_output_folder, &relative_path);
+        let progress = PackProgress::hidden();
+        let companion_files = tray_content.companion_files(tray_item);
+        let custom_content_files = self.get_custom_content_files(tray_item, &companion_files);
+        let packed = match self.format {
+            OutputFormat::Folder => {
+                pack_item_to_folder(&staging_output_folder, &relative_path, tray_item, &companion_files, &custom_content_files, self.anonymizer.as_ref(), &progress)
+            },
+            OutputFormat::Zip => {
+                pack_item_to_zip(&staging_output_folder, &relative_path, tray_item, &companion_files, &custom_content_files, self.anonymizer.as_ref(), &progress)
+            },
+            OutputFormat::S4pack => {
+                let bundle_item = BundleItem { tray_item, relative_path: relative_path.clone(), companion_files, custom_content_files };
+                fs::create_dir_all(staged_path.parent().unwrap_or(&staging_output_folder)).is_ok()
+                    && bundle::write_bundle(&staged_path, &[bundle_item], self.anonymizer.as_ref(), self.signing_key.as_ref(), &progress)
+            }
+        };
+        let replaced = packed && match replace_path(&staged_path, output_path) {
+            Ok(_) => true,
+            Err(_) => {
+                utils::print_error(format!("[{}] Couldn't move the new pack to {}!", tray_item.name, output_path.display()));
+                false
+            }
+        };
+        let _ = fs::remove_dir_all(&staging_output_folder);
+        let _ = fs::remove_dir(output_folder.join(constants::STAGING_FOLDERNAME));
+        return replaced;
+    }
+}
+
+pub fn pack(tray_override: Option<&Path>, mods_override: Option<&Path>, pack_args: &PackArgs, name_template: &NameTemplate, filter: &TrayItemFilter) -> Outcome {
+    if let Some(tray_folder) = utils::get_tray_folder(tray_override) {
+        let tray_content = read_selected_tray_files(&tray_folder, filter, pack_args.pick);
+        if tray_content.trayitem_files.is_empty() {
+            utils::print_info(String::from("There are no gallery items to pack."));
+            return Outcome::NothingToDo;
+        }
+        if let Some(packer) = Packer::new(&tray_folder, mods_override, pack_args, name_template) {
+            if let Some(output_folder) = utils::prepare_output_folder(pack_args.output.as_deref()) {
+                let progress = PackProgress::start(tray_content.trayitem_files.len());
+                let tray_items: Vec<&TrayItem> = tray_content.trayitem_files.iter().collect();
+                packer.pack_items(&output_folder, &tray_content, &tray_items, &progress);
+                return progress.finish();
+            }
+        }
+    }
+    return Outcome::Failure;
+}
+
+/// Tray folder content restricted to the items matching the filters and, when asked, picked by the user.
+pub fn read_selected_tray_files(tray_folder: &Path, filter: &TrayItemFilter, pick: bool) -> TrayFolderContent {
+    let mut tray_content = utils::read_tray_files(tray_folder);
+    if !filter.is_empty() {
+        let total = tray_content.trayitem_files.len();
+        tray_content.trayitem_files.retain(|tray_item| filter.matches(tray_item));
+        utils::print_info(format!("{} of {} gallery items match the filters.", tray_content.trayitem_files.len(), total));
+    }
+    if pick {
+        if let Some(selection) = prompts::pick_tray_items(&tray_content.trayitem_files) {
+            let mut index = 0;
+            tray_content.trayitem_files.retain(|_| {
+                let selected = selection.contains(&index);
+                index += 1;
+                return selected;
+            });
+        }
+    }
+    return tray_content;
+}
+
+/// Content of a file of the item as it should be packed, with the creator replaced when anonymizing.
+pub fn read_item_file(tray_item: &TrayItem, path: &Path, anonymizer: Option<&Anonymizer>) -> io::Result<Vec<u8>> {
+    let content = fs::read(path)?;
+    match anonymizer {
+        Some(anonymizer) => {
+            return anonymizer.anonymize_file(tray_item, path, &content)
+                .ok_or(io::Error::new(ErrorKind::InvalidData, "the creator couldn't be removed from the file"));
+        },
+        None => {
+            return Ok(content);
+        }
+    }
+}
+
+/// The item is copied into a staging folder first and only moved to its final place once every file was copied,
+/// so the output never contains a partial item that looks valid.
+fn pack_item_to_folder(output_folder: &Path, relative_path: &Path, tray_item: &TrayItem, companion_files: &[&TrayItemFile], custom_content_files: &[(&Path, PathBuf)], anonymizer: Option<&Anonymizer>, progress: &PackProgress) -> bool {
+    let tray_item_folder = output_folder.join(relative_path);
+    if tray_item_folder.exists() {
+        utils::print_error(format!("Output folder {} already exists, check that the naming template produces unique names!", tray_item_folder.display()));
+        utils::print_warning(format!("[{}] The whole gallery item will be skipped!", tray_item.name));
+        return false;
+    }
+    let staging_folder = output_folder.join(constants::STAGING_FOLDERNAME).join(format!("{:016x}", tray_item.id));
+    let _ = fs::remove_dir_all(&staging_folder);
+    if fs::create_dir_all(&staging_folder).is_err() {
+        utils::print_error(format!("Unable to create staging folder {}!", staging_folder.display()));
+        utils::print_warning(format!("[{}] The whole gallery item will be skipped!", tray_item.name));
+        return false;
+    }
+    let mut files: Vec<(&Path, io::Result<Vec<u8>>)> = vec![(Path::new(&tray_item.filename), read_item_file(tray_item, &tray_item.path, anonymizer))];
+    for companion_file in companion_files {
+        files.push((Path::new(&companion_file.filename), read_item_file(tray_item, &companion_file.path, anonymizer)));
+    }
+    for (package_path, package_relative_path) in custom_content_files {
+        files.push((package_relative_path, fs::read(package_path)));
+    }
+    for (filename, content) in files {
+        let target_path = staging_folder.join(filename);
+        let copied = content.and_then(|content| {
+            if let Some(parent) = target_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target_path, &content)?;
+            progress.add_bytes(content.len() as u64);
+            return Ok(());
+        });
+        if copied.is_err() {
+            utils::print_error(format!("[{}] Couldn't copy {} to gallery item folder!", tray_item.name, filename.display()));
+            utils::print_warning(format!("[{}] The whole gallery item will be skipped!", tray_item.name));
+            let _ = fs::remove_dir_all(&staging_folder);
+            return false;
+        }
+        utils::print_detail(format!("[{}] {} copied to gallery item folder.", tray_item.name, filename.display()));
+    }
+    let moved = match tray_item_folder.parent() {
+        Some(parent) => fs::create_dir_all(parent).and_then(|_| fs::rename(&staging_folder, &tray_item_folder)),
+        None => fs::rename(&staging_folder, &tray_item_folder)
+    };
+    if moved.is_err() {
+        utils::print_error(format!("Unable to move the gallery item to {}!", tray_item_folder.display()));
+        utils::print_warning(format!("[{}] The whole gallery item will be skipped!", tray_item.name));
+        let _ = fs::remove_dir_all(&staging_folder);
+        return false;
+    }
+    return true;
+}
+
+/// The archive is written under a temporary name and renamed once complete.
+fn pack_item_to_zip(output_folder: &Path, relative_path: &Path, tray_item: &TrayItem, companion_files: &[&TrayItemFile], custom_content_files: &[(&Path, PathBuf)], anonymizer: Option<&Anonymizer>, progress: &PackProgress) -> bool {
+    let archive_path = with_added_extension(&output_folder.join(relative_path), "zip");
+    if archive_path.exists() {
+        utils::print_error(format!("Archive {} already exists, check that the naming template produces unique names!", archive_path.display()));
+        utils::print_warning(format!("[{}] The whole gallery item will be skipped!", tray_item.name));
+        return false;
+    }
+    if let Some(parent) = archive_path.parent() {
+        if fs::create_dir_all(parent).is_err() {
+            utils::print_error(format!("Unable to create output folder {}!", parent.display()));
+            utils::print_warning(format!("[{}] The whole gallery item will be skipped!", tray_item.name));
+            return false;
+        }
+    }
+    let temporary_path = with_added_extension(&archive_path, "tmp");
+    let archive_file = match File::create(&temporary_path) {
+        Ok(archive_file) => archive_file,
+        Err(_) => {
+            utils::print_error(format!("Unable to create archive {}!", archive_path.display()));
+            utils::print_warning(format!("[{}] The whole gallery item will be skipped!", tray_item.name));
+            return false;
+        }
+    };
+    let mut archive = ZipWriter::new(archive_file);
+    let mut files: Vec<(String, io::Result<Vec<u8>>)> = vec![(tray_item.filename.clone(), read_item_file(tray_item, &tray_item.path, anonymizer))];
+    for companion_file in companion_files {
+        files.push((companion_file.filename.clone(), read_item_file(tray_item, &companion_file.path, anonymizer)));
+    }
+    for (package_path, package_relative_path) in custom_content_files {
+        let entry_name = package_relative_path.iter().map(|component| component.to_string_lossy()).collect::<Vec<_>>().join("/");
+        files.push((entry_name, fs::read(package_path)));
+    }
+    for (entry_name, content) in files {
+        match content.and_then(|content| add_file_to_zip(&mut archive, &content, &entry_name).map(|_| content.len())) {
+            Ok(size) => {
+                progress.add_bytes(size as u64);
+                utils::print_detail(format!("[{}] {} added to gallery item archive.", tray_item.name, entry_name));
+            },
+            Err(_) => {
+                utils::print_error(format!("[{}] Couldn't add {} to gallery item archive!", tray_item.name, entry_name));
+                utils::print_warning(format!("[{}] The whole gallery item will be skipped!", tray_item.name));
+                drop(archive);
+                let _ = fs::remove_file(&temporary_path);
+                return false;
+            }
+        }
+    }
+    let finished = archive.finish().map_err(io::Error::other).and_then(|archive_file| archive_file.sync_all());
+    if finished.and_then(|_| fs::rename(&temporary_path, &archive_path)).is_err() {
+        utils::print_error(format!("[{}] Couldn't write gallery item archive {}!", tray_item.name, archive_path.display()));
+        let _ = fs::remove_file(&temporary_path);
+        return false;
+    }
+    return true;
+}
+
+fn add_file_to_zip(archive: &mut ZipWriter<File>, content: &[u8], filename: &str) -> io::Result<()> {
+    archive.start_file(filename, SimpleFileOptions::default())?;
+    archive.write_all(content)?;
+    return Ok(());
+}
+
+fn with_added_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(".");
+    path.push(extension);
+    return PathBuf::from(path);
+}
+
+/// Moves `path` over `target`, which may be a folder. The previous target is only deleted once the new one is in place.
+fn replace_path(path: &Path, target: &Path) -> io::Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if !target.exists() {
+        return fs::rename(path, target);
+    }
+    let previous_path = with_added_extension(target, "old");
+    if previous_path.is_dir() {
+        fs::remove_dir_all(&previous_path)?;
+    } else if previous_path.exists() {
+        fs::remove_file(&previous_path)?;
+    }
+    fs::rename(target, &previous_path)?;
+    if let Err(e) = fs::rename(path, target) {
+        let _ = fs::rename(&previous_path, target);
+        return Err(e);
+    }
+    if previous_path.is_dir() {
+        return fs::remove_dir_all(&previous_path);
+    }
+    return fs::remove_file(&previous_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Read;
+
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::tray_metadata::tests::build_trayitem;
+
+    #[test]
+    fn watch_update_bundle_unpacks_into_the_tray_folder() {
+        let folder = env::temp_dir().join(format!("{}-watch-test-{}", constants::APPLICATION_NAME, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        let (tray_folder, output_folder, unpack_folder) = (folder.join("Tray"), folder.join("output"), folder.join("Unpacked"));
+        fs::create_dir_all(&tray_folder).unwrap();
+        fs::create_dir_all(&unpack_folder).unwrap();
+        let trayitem = build_trayitem("Villa Rosa", 0x1234_5678_9abc, "simmer42");
+        fs::write(tray_folder.join("0x00000001!0x00aa12f12d6202c5.trayitem"), &trayitem).unwrap();
+        fs::write(tray_folder.join("0x00000000!0x00aa12f12d6202c6.sgi"), [1, 2, 3]).unwrap();
+        let tray_content = utils::read_tray_files(&tray_folder);
+        let name_template = NameTemplate::parse(constants::DEFAULT_NAME_TEMPLATE).unwrap();
+        let packer = Packer { format: OutputFormat::S4pack, name_template: &name_template, signing_key: None, anonymizer: None, mods_index: None };
+        let tray_item = &tray_content.trayitem_files[0];
+        let output_path = packer.get_output_path(&output_folder, &packer.get_relative_path(tray_item));
+        fs::create_dir_all(output_path.parent().unwrap()).unwrap();
+        assert!(packer.pack_item_update(&output_folder, &tray_content, tray_item, &output_path));
+
+        let mut archive = ZipArchive::new(File::open(&output_path).unwrap()).unwrap();
+        let entry_names: Vec<String> = archive.file_names().map(String::from).collect();
+        assert!(entry_names.iter().all(|entry_name| !entry_name.starts_with('/')));
+        assert!(entry_names.iter().any(|entry_name| entry_name.ends_with("/0x00000001!0x00aa12f12d6202c5.trayitem")));
+        let mut manifest = String::new();
+        archive.by_name(constants::BUNDLE_MANIFEST_FILENAME).unwrap().read_to_string(&mut manifest).unwrap();
+        assert!(manifest.contains("Villa Rosa"));
+
+        let files = bundle::read_bundle(&output_path, &unpack_folder, None, &[], false).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].target_path, unpack_folder.join("0x00000001!0x00aa12f12d6202c5.trayitem"));
+        assert_eq!(files[0].content, trayitem);
+        assert_eq!(files[1].target_path, unpack_folder.join("0x00000000!0x00aa12f12d6202c6.sgi"));
+        assert_eq!(files[1].content, vec![1, 2, 3]);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
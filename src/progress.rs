@@ -0,0 +1,124 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
+
+use crate::tray_files::TrayItem;
+use crate::utils::Outcome;
+use crate::{constants, utils};
+
+/// Bar currently on screen, printed lines go above it instead of through it.
+static ACTIVE_BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// Overall progress of a pack run, drawn as one bar and summed up in a table at the end.
+pub struct PackProgress {
+    bar: ProgressBar,
+    started: Instant,
+    bytes: AtomicU64,
+    packed: Mutex<Vec<String>>,
+    skipped: Mutex<Vec<String>>,
+    warnings_before: usize,
+    errors_before: usize,
+}
+
+impl PackProgress {
+    pub fn start(total_items: usize) -> PackProgress {
+        let bar = ProgressBar::new(total_items as u64);
+        if let Ok(style) = ProgressStyle::with_template(constants::PROGRESS_TEMPLATE) {
+            bar.set_style(style.progress_chars(constants::PROGRESS_CHARS));
+        }
+        bar.set_message(format!("{} written", HumanBytes(0)));
+        if let Ok(mut active_bar) = ACTIVE_BAR.lock() {
+            *active_bar = Some(bar.clone());
+        }
+        return PackProgress {
+            bar,
+            started: Instant::now(),
+            bytes: AtomicU64::new(0),
+            packed: Mutex::new(Vec::new()),
+            skipped: Mutex::new(Vec::new()),
+            warnings_before: utils::get_warning_count(),
+            errors_before: utils::get_error_count(),
+        };
+    }
+
+    /// Counts without drawing anything, for the single item updates of `watch`.
+    pub fn hidden() -> PackProgress {
+        return PackProgress {
+            bar: ProgressBar::hidden(),
+            started: Instant::now(),
+            bytes: AtomicU64::new(0),
+            packed: Mutex::new(Vec::new()),
+            skipped: Mutex::new(Vec::new()),
+            warnings_before: utils::get_warning_count(),
+            errors_before: utils::get_error_count(),
+        };
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        let total = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.bar.set_message(format!("{} written", HumanBytes(total)));
+    }
+
+    pub fn finish_item(&self, tray_item: &TrayItem, packed: bool) {
+        let list = if packed { &self.packed } else { &self.skipped };
+        if let Ok(mut list) = list.lock() {
+            list.push(format!("{} (0x{:x})", tray_item.name, tray_item.id));
+        }
+        self.bar.inc(1);
+    }
+
+    /// Used when the bundle itself couldn't be written, none of the items made it to the output.
+    pub fn skip_packed_items(&self) {
+        if let (Ok(mut packed), Ok(mut skipped)) = (self.packed.lock(), self.skipped.lock()) {
+            skipped.append(&mut packed);
+        }
+    }
+
+    pub fn finish(self) -> Outcome {
+        self.bar.finish_and_clear();
+        if let Ok(mut active_bar) = ACTIVE_BAR.lock() {
+            *active_bar = None;
+        }
+        let packed = self.packed.into_inner().unwrap_or_default();
+        let mut skipped = self.skipped.into_inner().unwrap_or_default();
+        skipped.sort();
+        let rows = [
+            ("Packed", packed.len().to_string()),
+            ("Skipped", skipped.len().to_string()),
+            ("Warnings", (utils::get_warning_count() - self.warnings_before).to_string()),
+            ("Errors", (utils::get_error_count() - self.errors_before).to_string()),
+            ("Written", HumanBytes(self.bytes.into_inner()).to_string()),
+            ("Time", HumanDuration(self.started.elapsed()).to_string()),
+        ];
+        utils::print_info(String::from("Summary:"));
+        for (label, value) in rows {
+            utils::print_info(format!("    {:<10}{:>12}", label, value));
+        }
+        for name in skipped.iter().take(constants::SUMMARY_MAX_SKIPPED_ITEMS) {
+            utils::print_info(format!("    Skipped: {}", name));
+        }
+        if skipped.len() > constants::SUMMARY_MAX_SKIPPED_ITEMS {
+            utils::print_info(format!("    ... and {} more skipped items.", skipped.len() - constants::SUMMARY_MAX_SKIPPED_ITEMS));
+        }
+        return Outcome::from_counts(packed.len(), skipped.len());
+    }
+}
+
+/// Prints a line without tearing the progress bar, if one is shown. A closed stdout, like a pipe into `head`,
+/// isn't worth stopping the work for.
+pub fn print_line(line: &str) {
+    let active_bar = ACTIVE_BAR.lock().ok().and_then(|active_bar| active_bar.clone());
+    match active_bar {
+        Some(bar) => {
+            bar.suspend(|| {
+                let _ = writeln!(io::stdout(), "{}", line);
+            });
+        },
+        None => {
+            let _ = writeln!(io::stdout(), "{}", line);
+        }
+    }
+}
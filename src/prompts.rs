@@ -0,0 +1,133 @@
+use std::io::{stdin, stdout, IsTerminal};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use console::Term;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::{Confirm, MultiSelect, Select};
+
+use crate::tray_files::TrayItem;
+use crate::utils;
+
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// With --yes nothing is ever asked, even on a terminal.
+pub fn set_assume_yes(assume_yes: bool) {
+    ASSUME_YES.store(assume_yes, Ordering::Relaxed);
+}
+
+pub fn is_interactive() -> bool {
+    return !ASSUME_YES.load(Ordering::Relaxed) && stdin().is_terminal() && stdout().is_terminal();
+}
+
+pub fn confirm(message: String) -> bool {
+    if ASSUME_YES.load(Ordering::Relaxed) {
+        utils::print_info(format!("{} Answering yes (--yes).", message));
+        return true;
+    }
+    if !is_interactive() {
+        utils::print_warning(format!("{} The console isn't interactive, answering no.", message));
+        return false;
+    }
+    match Confirm::with_theme(&ColorfulTheme::default()).with_prompt(message).default(false).interact() {
+        Ok(answer) => {
+            return answer;
+        },
+        Err(_) => {
+            utils::print_error(String::from("Couldn't read input."));
+            return false;
+        }
+    }
+}
+
+/// Returns the indexes of the selected items, or `None` when the list can't be shown and every item should be kept.
+pub fn pick_tray_items(tray_items: &[TrayItem]) -> Option<Vec<usize>> {
+    if !is_interactive() {
+        utils::print_warning(String::from("The console isn't interactive, every gallery item will be packed."));
+        return None;
+    }
+    let labels: Vec<String> = tray_items.iter().map(|tray_item| {
+        let type_name = utils::get_type_folder_name(tray_item.file_type).unwrap_or("unknown");
+        if tray_item.creator_name.is_empty() {
+            return format!("{:<10} {}", type_name, tray_item.name);
+        }
+        return format!("{:<10} {} (by {})", type_name, tray_item.name, tray_item.creator_name);
+    }).collect();
+    let selection = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select the gallery items to pack (space to toggle, a to toggle all, enter to confirm, esc to cancel)")
+        .items(&labels)
+        .max_length(20)
+        .interact_opt();
+    match selection {
+        Ok(Some(selection)) => {
+            return Some(selection);
+        },
+        Ok(None) => {
+            utils::print_warning(String::from("Selection cancelled, no gallery item will be packed."));
+            return Some(Vec::new());
+        },
+        Err(_) => {
+            utils::print_error(String::from("Couldn't read input, no gallery item will be packed."));
+            return Some(Vec::new());
+        }
+    }
+}
+
+/// Returns the index of the chosen answer, the first one is the default used when nobody can answer.
+pub fn select(message: String, choices: &[&str]) -> Option<usize> {
+    if !is_interactive() {
+        utils::print_info(format!("{} Answering \"{}\".", message, choices[0]));
+        return Some(0);
+    }
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(message)
+        .items(choices)
+        .default(0)
+        .interact_opt();
+    match selection {
+        Ok(selection) => {
+            return selection;
+        },
+        Err(_) => {
+            utils::print_error(String::from("Couldn't read input."));
+            return None;
+        }
+    }
+}
+
+pub fn pick_tray_folder(mut tray_folders: Vec<PathBuf>) -> Option<PathBuf> {
+    if !is_interactive() {
+        utils::print_info(String::from("Found several Tray folders:"));
+        for tray_folder in &tray_folders {
+            utils::print_info(format!("  {}", tray_folder.display()));
+        }
+        utils::print_warning(format!("The console isn't interactive, using {}. Use --tray to choose another one.", tray_folders[0].display()));
+        return Some(tray_folders.remove(0));
+    }
+    let labels: Vec<String> = tray_folders.iter().map(|tray_folder| tray_folder.display().to_string()).collect();
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Found several Tray folders, which one should be used?")
+        .items(&labels)
+        .default(0)
+        .interact_opt();
+    match selection {
+        Ok(Some(index)) => {
+            return Some(tray_folders.remove(index));
+        },
+        Ok(None) => {
+            return None;
+        },
+        Err(_) => {
+            utils::print_error(String::from("Couldn't read input."));
+            return None;
+        }
+    }
+}
+
+pub fn wait_for_key(message: String) {
+    if !is_interactive() {
+        return;
+    }
+    utils::print_info(message);
+    let _ = Term::stdout().read_key();
+}
@@ -0,0 +1,351 @@
+#[derive(Clone, Copy)]
+pub enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    LengthDelimited(&'a [u8]),
+    Fixed32(u32),
+}
+
+pub struct Field<'a> {
+    pub number: u32,
+    pub value: WireValue<'a>,
+}
+
+pub fn read_varint(content: &[u8], offset: usize) -> Option<(u64, usize)> {
+    let mut result: u64 = 0;
+    let mut shift = 0;
+    let mut position = offset;
+    while position < content.len() && shift < 64 {
+        let byte = content[position];
+        result |= ((byte & 0x7F) as u64) << shift;
+        position += 1;
+        if byte & 0x80 == 0 {
+            return Some((result, position));
+        }
+        shift += 7;
+    }
+    return None;
+}
+
+/// A field with the bytes it takes in the message, from its key to the end of its value.
+pub struct FieldSpan<'a> {
+    pub start: usize,
+    pub end: usize,
+    pub field: Field<'a>,
+}
+
+pub fn read_fields(content: &[u8]) -> Option<Vec<Field<'_>>> {
+    let spans = read_field_spans(content)?;
+    return Some(spans.into_iter().map(|span| span.field).collect());
+}
+
+pub fn read_field_spans(content: &[u8]) -> Option<Vec<FieldSpan<'_>>> {
+    let mut spans: Vec<FieldSpan> = Vec::new();
+    let mut position = 0;
+    while position < content.len() {
+        let (field, end) = read_field(content, position)?;
+        spans.push(FieldSpan { start: position, end, field });
+        position = end;
+    }
+    return Some(spans);
+}
+
+/// Reads the field starting at `position` and returns it with the position right after it.
+fn read_field(content: &[u8], position: usize) -> Option<(Field<'_>, usize)> {
+    let (key, next) = read_varint(content, position)?;
+    let number = (key >> 3) as u32;
+    if number == 0 {
+        return None;
+    }
+    match key & 0x07 {
+        0 => {
+            let (varint, end) = read_varint(content, next)?;
+            return Some((Field { number, value: WireValue::Varint(varint) }, end));
+        },
+        1 => {
+            let bytes: [u8; 8] = content.get(next..next + 8)?.try_into().ok()?;
+            return Some((Field { number, value: WireValue::Fixed64(u64::from_le_bytes(bytes)) }, next + 8));
+        },
+        2 => {
+            let (length, next) = read_varint(content, next)?;
+            let end = next.checked_add(length as usize)?;
+            return Some((Field { number, value: WireValue::LengthDelimited(content.get(next..end)?) }, end));
+        },
+        5 => {
+            let bytes: [u8; 4] = content.get(next..next + 4)?.try_into().ok()?;
+            return Some((Field { number, value: WireValue::Fixed32(u32::from_le_bytes(bytes)) }, next + 4));
+        },
+        _ => {
+            return None;
+        }
+    }
+}
+
+pub fn get_wire_type_name(value: &WireValue) -> &'static str {
+    match value {
+        WireValue::Varint(_) => {
+            return "varint";
+        },
+        WireValue::Fixed64(_) => {
+            return "fixed64";
+        },
+        WireValue::LengthDelimited(_) => {
+            return "bytes";
+        },
+        WireValue::Fixed32(_) => {
+            return "fixed32";
+        }
+    }
+}
+
+/// Short human readable form of a value, length delimited values are shown as text when they look like text.
+pub fn format_value(value: &WireValue) -> String {
+    match value {
+        WireValue::Varint(value) => {
+            return format!("{} (0x{:x})", value, value);
+        },
+        WireValue::Fixed64(value) => {
+            return format!("0x{:016x}", value);
+        },
+        WireValue::Fixed32(value) => {
+            return format!("0x{:08x}", value);
+        },
+        WireValue::LengthDelimited(bytes) => {
+            if let Some(text) = as_text(bytes) {
+                return format!("\"{}\"", text);
+            }
+            if bytes.len() > 32 {
+                return format!("{}... ({} bytes)", hex::encode(&bytes[..32]), bytes.len());
+            }
+            return format!("{} ({} bytes)", hex::encode(bytes), bytes.len());
+        }
+    }
+}
+
+fn as_text(bytes: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    if text.chars().any(|character| character.is_control() && character != '\n') {
+        return None;
+    }
+    return Some(text);
+}
+
+/// Tree of every field of a message whose schema is unknown, one line per field, nested messages indented.
+/// Length delimited values are guessed: text when printable, a message when they parse as one, packed varints,
+/// and raw bytes otherwise. `base_offset` is added to every offset so they point into the whole file.
+pub fn dump(content: &[u8], base_offset: usize, max_depth: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    dump_message(content, base_offset, 0, max_depth, &mut lines);
+    return lines;
+}
+
+fn dump_message(content: &[u8], base_offset: usize, depth: usize, max_depth: usize, lines: &mut Vec<String>) {
+    let indent = "    ".repeat(depth);
+    let mut position = 0;
+    while position < content.len() {
+        match read_field(content, position) {
+            Some((field, end)) => {
+                let prefix = format!("{}@0x{:04x} {:>3} ", indent, base_offset + position, field.number);
+                match field.value {
+                    WireValue::LengthDelimited(bytes) => {
+                        dump_length_delimited(bytes, base_offset + end - bytes.len(), &prefix, depth, max_depth, lines);
+                    },
+                    value => {
+                        lines.push(format!("{}{:<8} {}", prefix, get_wire_type_name(&value), describe_number(&value)));
+                    }
+                }
+                position = end;
+            },
+            None => {
+                // Keeps what was decoded so far, the rest is shown as is.
+                let rest = &content[position..];
+                lines.push(format!("{}@0x{:04x} not a field, {} bytes left: {}", indent, base_offset + position, rest.len(), hex::encode(&rest[..rest.len().min(32)])));
+                return;
+            }
+        }
+    }
+}
+
+fn dump_length_delimited(bytes: &[u8], offset: usize, prefix: &str, depth: usize, max_depth: usize, lines: &mut Vec<String>) {
+    if bytes.is_empty() {
+        lines.push(format!("{}empty", prefix));
+        return;
+    }
+    if let Some(text) = as_text(bytes) {
+        lines.push(format!("{}string   \"{}\"", prefix, text));
+        return;
+    }
+    if depth < max_depth && looks_like_message(bytes) {
+        lines.push(format!("{}message  ({} bytes)", prefix, bytes.len()));
+        dump_message(bytes, offset, depth + 1, max_depth, lines);
+        return;
+    }
+    if let Some(values) = read_packed_varints(bytes) {
+        let mut shown: Vec<String> = values.iter().take(16).map(|value| value.to_string()).collect();
+        if values.len() > 16 {
+            shown.push(format!("... {} more", values.len() - 16));
+        }
+        lines.push(format!("{}packed   [{}]", prefix, shown.join(", ")));
+        return;
+    }
+    lines.push(format!("{}bytes    {}", prefix, format_value(&WireValue::LengthDelimited(bytes))));
+}
+
+/// Any bytes may happen to parse, but real messages of the game only use small field numbers.
+fn looks_like_message(bytes: &[u8]) -> bool {
+    match read_field_spans(bytes) {
+        Some(spans) => {
+            return spans.iter().all(|span| span.field.number < 0x1000);
+        },
+        None => {
+            return false;
+        }
+    }
+}
+
+fn read_packed_varints(bytes: &[u8]) -> Option<Vec<u64>> {
+    let mut values: Vec<u64> = Vec::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        let (value, next) = read_varint(bytes, position)?;
+        values.push(value);
+        position = next;
+    }
+    return Some(values);
+}
+
+/// The same bits read as the other types they could be.
+fn describe_number(value: &WireValue) -> String {
+    match value {
+        WireValue::Varint(value) => {
+            if (*value as i64) < 0 {
+                return format!("{} (0x{:x}, signed {})", value, value, *value as i64);
+            }
+            return format!("{} (0x{:x})", value, value);
+        },
+        WireValue::Fixed64(value) => {
+            return format!("0x{:016x} ({}{})", value, value, describe_float(f64::from_bits(*value)));
+        },
+        WireValue::Fixed32(value) => {
+            return format!("0x{:08x} ({}{})", value, value, describe_float(f32::from_bits(*value) as f64));
+        },
+        WireValue::LengthDelimited(_) => {
+            return format_value(value);
+        }
+    }
+}
+
+/// Integers read as floats are tiny or huge, only values a game would store are worth showing.
+fn describe_float(value: f64) -> String {
+    if value == 0.0 || (value.is_finite() && (1e-6..1e9).contains(&value.abs())) {
+        return format!(", float {}", value);
+    }
+    return String::new();
+}
+
+pub fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+/// Encodes the fields back in the given order, so a message read and written unchanged keeps the same bytes.
+pub fn write_fields(fields: &[Field]) -> Vec<u8> {
+    let mut output: Vec<u8> = Vec::new();
+    for field in fields {
+        let number = (field.number as u64) << 3;
+        match field.value {
+            WireValue::Varint(value) => {
+                write_varint(&mut output, number);
+                write_varint(&mut output, value);
+            },
+            WireValue::Fixed64(value) => {
+                write_varint(&mut output, number | 1);
+                output.extend_from_slice(&value.to_le_bytes());
+            },
+            WireValue::LengthDelimited(bytes) => {
+                write_varint(&mut output, number | 2);
+                write_varint(&mut output, bytes.len() as u64);
+                output.extend_from_slice(bytes);
+            },
+            WireValue::Fixed32(value) => {
+                write_varint(&mut output, number | 5);
+                output.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    return output;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_shows_every_branch() {
+        let nested = write_fields(&[Field { number: 1, value: WireValue::Varint(7) }, Field { number: 2, value: WireValue::LengthDelimited(b"chair") }]);
+        let content = write_fields(&[
+            Field { number: 1, value: WireValue::Varint(150) },
+            Field { number: 2, value: WireValue::Varint(u64::MAX - 1) },
+            Field { number: 3, value: WireValue::Fixed64(1.5f64.to_bits()) },
+            Field { number: 4, value: WireValue::Fixed32(1.5f32.to_bits()) },
+            Field { number: 5, value: WireValue::Fixed32(u32::MAX) },
+            Field { number: 6, value: WireValue::LengthDelimited(b"") },
+            Field { number: 7, value: WireValue::LengthDelimited(b"Villa Rosa") },
+            Field { number: 8, value: WireValue::LengthDelimited(&nested) },
+            Field { number: 9, value: WireValue::LengthDelimited(&[0x96, 0x01, 0x03, 0xC8, 0x01]) },
+            Field { number: 10, value: WireValue::LengthDelimited(&[0xFF; 11]) },
+        ]);
+        assert_eq!(dump(&content, 8, 4), vec![
+            "@0x0008   1 varint   150 (0x96)",
+            "@0x000b   2 varint   18446744073709551614 (0xfffffffffffffffe, signed -2)",
+            "@0x0016   3 fixed64  0x3ff8000000000000 (4609434218613702656, float 1.5)",
+            "@0x001f   4 fixed32  0x3fc00000 (1069547520, float 1.5)",
+            "@0x0024   5 fixed32  0xffffffff (4294967295)",
+            "@0x0029   6 empty",
+            "@0x002b   7 string   \"Villa Rosa\"",
+            "@0x0037   8 message  (9 bytes)",
+            "    @0x0039   1 varint   7 (0x7)",
+            "    @0x003b   2 string   \"chair\"",
+            "@0x0042   9 packed   [150, 3, 200]",
+            "@0x0049  10 bytes    ffffffffffffffffffffff (11 bytes)",
+        ]);
+    }
+
+    #[test]
+    fn dump_stops_decoding_messages_at_the_maximum_depth() {
+        let innermost = write_fields(&[Field { number: 1, value: WireValue::Varint(7) }]);
+        let inner = write_fields(&[Field { number: 1, value: WireValue::LengthDelimited(&innermost) }]);
+        let content = write_fields(&[Field { number: 1, value: WireValue::LengthDelimited(&inner) }]);
+        assert_eq!(dump(&content, 0, 1), vec![
+            "@0x0000   1 message  (4 bytes)",
+            "    @0x0002   1 packed   [8, 7]",
+        ]);
+        assert_eq!(dump(&content, 0, 2).last().unwrap(), "        @0x0004   1 varint   7 (0x7)");
+        assert_eq!(dump(&content, 0, 0), vec!["@0x0000   1 packed   [10, 2, 8, 7]"]);
+    }
+
+    #[test]
+    fn dump_keeps_what_it_decoded_before_damaged_bytes() {
+        let mut content = write_fields(&[Field { number: 1, value: WireValue::Varint(1) }]);
+        content.extend_from_slice(&[0x0F, 0x01]);
+        assert_eq!(dump(&content, 0, 4), vec!["@0x0000   1 varint   1 (0x1)", "@0x0002 not a field, 2 bytes left: 0f01"]);
+    }
+
+    #[test]
+    fn dump_shortens_long_packed_lists() {
+        let values: Vec<u8> = (0x80..0x94).flat_map(|value| [value, 0x01]).collect();
+        let lines = dump(&write_fields(&[Field { number: 1, value: WireValue::LengthDelimited(&values) }]), 0, 4);
+        assert_eq!(lines, vec!["@0x0000   1 packed   [128, 129, 130, 131, 132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142, 143, ... 4 more]"]);
+    }
+
+    #[test]
+    fn looks_like_message_refuses_large_field_numbers() {
+        assert!(looks_like_message(&write_fields(&[Field { number: 0xFFF, value: WireValue::Varint(1) }])));
+        assert!(!looks_like_message(&write_fields(&[Field { number: 0x1000, value: WireValue::Varint(1) }])));
+        assert!(!looks_like_message(&[0x0F, 0x01]));
+    }
+}
@@ -0,0 +1,200 @@
+use std::fs;
+use std::path::PathBuf;
+
+use directories::ProjectDirs;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::Outcome;
+use crate::{constants, utils};
+
+/// Stored next to the manifest inside the bundle, the manifest lists the hash of every file so signing it covers them all.
+#[derive(Deserialize, Serialize)]
+pub struct BundleSignature {
+    pub public_key: String,
+    pub signature: String,
+}
+
+pub fn get_signing_key_path() -> Option<PathBuf> {
+    let project_dirs = ProjectDirs::from("", "", constants::APPLICATION_NAME)?;
+    return Some(project_dirs.data_dir().join(constants::SIGNING_KEY_FILENAME));
+}
+
+/// Creates the local signing key, an existing key is only replaced when forced since bundles signed with it couldn't be trusted anymore.
+pub fn generate_key(force: bool) -> Outcome {
+    let key_path = match get_signing_key_path() {
+        Some(key_path) => key_path,
+        None => {
+            utils::print_error(String::from("Couldn't find the data directory to store the signing key!"));
+            return Outcome::Failure;
+        }
+    };
+    if !force {
+        if let Ok(signing_key) = load_signing_key() {
+            utils::print_warning(format!("A signing key already exists, use --force to replace it. Its public key is {}", format_public_key(&signing_key.verifying_key())));
+            return Outcome::NothingToDo;
+        }
+    }
+    let mut secret = [0u8; 32];
+    if getrandom::fill(&mut secret).is_err() {
+        utils::print_error(String::from("Couldn't get random bytes from the system to create the signing key!"));
+        return Outcome::Failure;
+    }
+    let signing_key = SigningKey::from_bytes(&secret);
+    let written = match key_path.parent() {
+        Some(parent) => fs::create_dir_all(parent).and_then(|_| write_secret_file(&key_path, &hex::encode(secret))),
+        None => write_secret_file(&key_path, &hex::encode(secret))
+    };
+    match written {
+        Ok(_) => {
+            utils::print_success(format!("Signing key written to {}.", key_path.display()));
+            utils::print_info(format!("Share this public key with the people receiving your packs: {}", format_public_key(&signing_key.verifying_key())));
+            return Outcome::Success;
+        },
+        Err(_) => {
+            utils::print_error(format!("Couldn't write the signing key to {}!", key_path.display()));
+            return Outcome::Failure;
+        }
+    }
+}
+
+pub fn load_signing_key() -> Result<SigningKey, String> {
+    let key_path = get_signing_key_path().ok_or(String::from("Couldn't find the data directory holding the signing key"))?;
+    let content = fs::read_to_string(&key_path).map_err(|_| String::from("There is no signing key yet, create one with the keygen command"))?;
+    let secret: [u8; 32] = hex::decode(content.trim()).ok()
+        .and_then(|secret| secret.try_into().ok())
+        .ok_or(format!("The signing key {} is damaged", key_path.display()))?;
+    return Ok(SigningKey::from_bytes(&secret));
+}
+
+pub fn sign(content: &[u8], signing_key: &SigningKey) -> BundleSignature {
+    return BundleSignature {
+        public_key: format_public_key(&signing_key.verifying_key()),
+        signature: hex::encode(signing_key.sign(content).to_bytes()),
+    };
+}
+
+/// Checks the signature itself, whether the key is trusted is up to the caller.
+pub fn verify(content: &[u8], bundle_signature: &BundleSignature) -> Result<VerifyingKey, String> {
+    let public_key = parse_public_key(&bundle_signature.public_key)?;
+    let signature: [u8; 64] = hex::decode(&bundle_signature.signature).ok()
+        .and_then(|signature| signature.try_into().ok())
+        .ok_or(String::from("The bundle signature is malformed"))?;
+    if public_key.verify(content, &Signature::from_bytes(&signature)).is_err() {
+        return Err(String::from("The bundle signature doesn't match its manifest, the bundle was modified after signing"));
+    }
+    return Ok(public_key);
+}
+
+/// A bad signature always stops the install, a missing or untrusted one only does when a signature is required.
+pub fn check(manifest_content: &[u8], signature_content: Option<&[u8]>, trusted_keys: &[VerifyingKey], require_signature: bool) -> Result<(), String> {
+    let signature_content = match signature_content {
+        Some(signature_content) => signature_content,
+        None => {
+            if require_signature {
+                return Err(String::from("The bundle isn't signed"));
+            }
+            utils::print_warning(String::from("The bundle isn't signed, its sender can't be verified."));
+            return Ok(());
+        }
+    };
+    let bundle_signature: BundleSignature = std::str::from_utf8(signature_content).ok()
+        .and_then(|signature_content| toml::from_str(signature_content).ok())
+        .ok_or(String::from("The bundle signature is malformed"))?;
+    let public_key = verify(manifest_content, &bundle_signature)?;
+    if trusted_keys.contains(&public_key) {
+        utils::print_success(format!("The bundle is signed by trusted key {}.", format_public_key(&public_key)));
+        return Ok(());
+    }
+    if require_signature {
+        return Err(format!("The bundle is signed by untrusted key {}", format_public_key(&public_key)));
+    }
+    utils::print_warning(format!("The bundle is signed by key {} which isn't in the trusted keys.", format_public_key(&public_key)));
+    return Ok(());
+}
+
+pub fn parse_public_key(value: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = hex::decode(value.trim()).ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(format!("Invalid public key \"{}\", expected 64 hexadecimal digits", value))?;
+    return VerifyingKey::from_bytes(&bytes).map_err(|_| format!("Invalid public key \"{}\"", value));
+}
+
+pub fn format_public_key(public_key: &VerifyingKey) -> String {
+    return hex::encode(public_key.as_bytes());
+}
+
+#[cfg(unix)]
+fn write_secret_file(path: &std::path::Path, content: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut file = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    return file.write_all(content.as_bytes());
+}
+
+#[cfg(not(unix))]
+fn write_secret_file(path: &std::path::Path, content: &str) -> std::io::Result<()> {
+    return fs::write(path, content);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &[u8] = b"format_version = 1\ncreated = \"2026-10-19T08:00:00+00:00\"\n";
+
+    fn get_signing_key(seed: u8) -> SigningKey {
+        return SigningKey::from_bytes(&[seed; 32]);
+    }
+
+    fn get_signature_content(content: &[u8], signing_key: &SigningKey) -> Vec<u8> {
+        return toml::to_string(&sign(content, signing_key)).unwrap().into_bytes();
+    }
+
+    #[test]
+    fn signed_manifest_verifies_with_a_trusted_key() {
+        let signing_key = get_signing_key(7);
+        let bundle_signature = sign(MANIFEST, &signing_key);
+        assert!(verify(MANIFEST, &bundle_signature).unwrap() == signing_key.verifying_key());
+        let signature_content = get_signature_content(MANIFEST, &signing_key);
+        assert!(check(MANIFEST, Some(&signature_content), &[signing_key.verifying_key()], true).is_ok());
+    }
+
+    #[test]
+    fn tampered_manifest_is_refused() {
+        let signing_key = get_signing_key(7);
+        let signature_content = get_signature_content(MANIFEST, &signing_key);
+        let mut tampered_manifest = MANIFEST.to_vec();
+        tampered_manifest[17] = b'2';
+        let error = check(&tampered_manifest, Some(&signature_content), &[signing_key.verifying_key()], false).unwrap_err();
+        assert!(error.contains("modified after signing"), "{}", error);
+    }
+
+    #[test]
+    fn untrusted_key_is_refused_when_a_signature_is_required() {
+        let signature_content = get_signature_content(MANIFEST, &get_signing_key(7));
+        let trusted_keys = [get_signing_key(8).verifying_key()];
+        let error = check(MANIFEST, Some(&signature_content), &trusted_keys, true).unwrap_err();
+        assert!(error.contains("untrusted key"), "{}", error);
+        assert!(check(MANIFEST, Some(&signature_content), &trusted_keys, false).is_ok());
+    }
+
+    #[test]
+    fn missing_signature_is_refused_when_a_signature_is_required() {
+        let trusted_keys = [get_signing_key(7).verifying_key()];
+        assert_eq!(check(MANIFEST, None, &trusted_keys, true).unwrap_err(), "The bundle isn't signed");
+        assert!(check(MANIFEST, None, &trusted_keys, false).is_ok());
+        assert_eq!(check(MANIFEST, Some(b"not a signature"), &trusted_keys, false).unwrap_err(), "The bundle signature is malformed");
+    }
+
+    #[test]
+    fn parse_public_key_refuses_malformed_keys() {
+        let public_key = get_signing_key(7).verifying_key();
+        assert!(parse_public_key(&format!(" {} ", format_public_key(&public_key))).unwrap() == public_key);
+        let valid = format_public_key(&public_key);
+        for value in ["", "zz", &valid[..62], &format!("{}00", valid), &valid.replacen(&valid[..1], "g", 1)] {
+            assert!(parse_public_key(value).is_err(), "{} was accepted", value);
+        }
+    }
+}
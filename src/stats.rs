@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+use indicatif::HumanBytes;
+
+use crate::filters::TrayItemFilter;
+use crate::tray_files::TrayItem;
+use crate::utils::Outcome;
+use crate::{constants, packing, utils};
+
+/// Labels of the file kinds, in the order of the sizes in `ItemStats`: trayitems, household, lot and room binaries,
+/// their .hhi, .bpi and .rmi thumbnails, and the numbered .sgi thumbnails.
+const FILE_KINDS: [&str; 4] = ["trayitem", "binary", "thumbnails", "sgi"];
+
+struct ItemStats<'a> {
+    tray_item: &'a TrayItem,
+    sizes: [u64; 4],
+}
+
+impl ItemStats<'_> {
+    fn total(&self) -> u64 {
+        return self.sizes.iter().sum();
+    }
+}
+
+/// Reports how many items the Tray folder holds, the space they use and which ones are the largest and oldest.
+pub fn stats(tray_folder: &Path, filter: &TrayItemFilter, top: usize) -> Outcome {
+    let tray_content = packing::read_selected_tray_files(tray_folder, filter, false);
+    if tray_content.trayitem_files.is_empty() {
+        utils::print_info(String::from("There are no gallery items in the Tray folder."));
+        return Outcome::NothingToDo;
+    }
+    let mut kind_counts = [0usize; 4];
+    let mut kind_sizes = [0u64; 4];
+    let mut item_stats: Vec<ItemStats> = Vec::new();
+    let mut item_paths: HashSet<&Path> = HashSet::new();
+    for tray_item in &tray_content.trayitem_files {
+        let mut stats = ItemStats { tray_item, sizes: [0; 4] };
+        let companion_files = tray_content.companion_files(tray_item);
+        let paths = std::iter::once(tray_item.path.as_path()).chain(companion_files.iter().map(|companion_file| companion_file.path.as_path()));
+        for path in paths {
+            let kind = get_file_kind(path);
+            stats.sizes[kind] += get_file_size(path);
+            kind_counts[kind] += 1;
+            item_paths.insert(path);
+        }
+        for (kind, size) in stats.sizes.iter().enumerate() {
+            kind_sizes[kind] += size;
+        }
+        item_stats.push(stats);
+    }
+    let mut type_counts: Vec<String> = Vec::new();
+    for file_type in [constants::HOUSEHOLD_TRAYITEM_TYPE, constants::PLOT_TRAYITEM_TYPE, constants::ROOM_TRAYITEM_TYPE] {
+        let count = item_stats.iter().filter(|stats| stats.tray_item.file_type == file_type).count();
+        type_counts.push(format!("{} {}", utils::get_type_folder_name(file_type).unwrap_or("unknown"), count));
+    }
+    utils::print_info(format!("Gallery items: {} ({})", item_stats.len(), type_counts.join(", ")));
+    utils::print_info(format!("Disk usage: {} in {} files", HumanBytes(kind_sizes.iter().sum()), kind_counts.iter().sum::<usize>()));
+    for (kind, label) in FILE_KINDS.iter().enumerate() {
+        utils::print_info(format!("    {:<12}{:>7} files{:>12}", label, kind_counts[kind], HumanBytes(kind_sizes[kind]).to_string()));
+    }
+    // With filters, the files of the items left out would all look orphaned.
+    if filter.is_empty() {
+        let orphan_sizes: Vec<u64> = tray_content.unknown_files.iter().chain(tray_content.sgi_files.iter())
+            .filter(|tray_file| !item_paths.contains(tray_file.path.as_path()))
+            .map(|tray_file| get_file_size(&tray_file.path))
+            .collect();
+        if !orphan_sizes.is_empty() {
+            utils::print_info(format!("    {:<12}{:>7} files{:>12}  (not part of any gallery item)", "orphaned", orphan_sizes.len(), HumanBytes(orphan_sizes.iter().sum()).to_string()));
+        }
+    }
+    item_stats.sort_by(|a, b| b.total().cmp(&a.total()).then(a.tray_item.id.cmp(&b.tray_item.id)));
+    utils::print_info(String::from("Largest items:"));
+    for stats in item_stats.iter().take(top) {
+        let details: Vec<String> = FILE_KINDS.iter().enumerate().skip(1)
+            .map(|(kind, label)| format!("{} {}", label, HumanBytes(stats.sizes[kind])))
+            .collect();
+        utils::print_info(format!("    {:>10}  {} (0x{:x}): {}", HumanBytes(stats.total()).to_string(), stats.tray_item.name, stats.tray_item.id, details.join(", ")));
+    }
+    item_stats.sort_by(|a, b| a.tray_item.modified.cmp(&b.tray_item.modified).then(a.tray_item.id.cmp(&b.tray_item.id)));
+    utils::print_info(String::from("Oldest untouched items:"));
+    for stats in item_stats.iter().take(top) {
+        let modified: DateTime<Local> = DateTime::from(stats.tray_item.modified);
+        utils::print_info(format!("    {}  {} (0x{:x})", modified.format("%Y-%m-%d"), stats.tray_item.name, stats.tray_item.id));
+    }
+    return Outcome::Success;
+}
+
+fn get_file_kind(path: &Path) -> usize {
+    match path.extension().and_then(|extension| extension.to_str()).unwrap_or("") {
+        constants::TRAYITEM_EXT => {
+            return 0;
+        },
+        constants::HOUSEHOLDBINARY_EXT | constants::BLUEPRINT_EXT | constants::ROOM_EXT => {
+            return 1;
+        },
+        constants::HHI_EXT | constants::BPI_EXT | constants::RMI_EXT => {
+            return 2;
+        },
+        _ => {
+            return 3;
+        }
+    }
+}
+
+fn get_file_size(path: &Path) -> u64 {
+    return fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+}
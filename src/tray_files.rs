@@ -1,8 +1,7 @@
-use std::{fs, path::PathBuf};
-
-use colored::Colorize;
+use std::{fs, path::{Path, PathBuf}, time::SystemTime};
 
 use crate::{constants, utils};
+use crate::tray_metadata::TrayMetadata;
 
 pub struct TrayFolderContent {
     pub trayitem_files: Vec<TrayItem>,
@@ -10,54 +9,82 @@ pub struct TrayFolderContent {
     pub sgi_files: Vec<TrayItemFile>,
 }
 
+impl TrayFolderContent {
+    /// Binary files sharing the item id, followed by the thumbnails numbered right after it.
+    pub fn companion_files(&self, tray_item: &TrayItem) -> Vec<&TrayItemFile> {
+        let mut companion_files: Vec<&TrayItemFile> = self.unknown_files.iter().filter(|unknown_file| unknown_file.id == tray_item.id).collect();
+        let mut expected_id = tray_item.id + 1;
+        loop {
+            let sgi_files: Vec<&TrayItemFile> = self.sgi_files.iter().filter(|sgi_file| sgi_file.id == expected_id).collect();
+            if sgi_files.is_empty() {
+                break;
+            }
+            companion_files.extend(sgi_files);
+            expected_id += 1;
+        }
+        return companion_files;
+    }
+}
+
+#[derive(Clone)]
 pub struct TrayItem {
     pub path: PathBuf,
     pub name: String,
+    pub creator_name: String,
+    pub creator_id: u64,
     pub id: u64,
     pub file_type: u32,
     pub filename: String,
+    pub modified: SystemTime,
 }
 
 impl TrayItem {
-    pub fn new(path: &PathBuf) -> Option<TrayItem> {
-        if let Some((file_type, id)) = utils::extract_id_and_type(&path) {
+    pub fn new(path: &Path) -> Option<TrayItem> {
+        if let Some((file_type, id)) = utils::extract_id_and_type(path) {
             match fs::read(path) {
                 Ok(content) => {
                     if let Some(file_name) = path.file_name() {
                         if let Some(file_name) = file_name.to_str() {
-                            let name_size: u8;
-                            let offset: usize;
                             match file_type {
-                                constants::HOUSEHOLD_TRAYITEM_TYPE => {
-                                    name_size = content[constants::HOUSEHOLD_TRAYITEM_NAME_OFFSET];
-                                    offset = constants::HOUSEHOLD_TRAYITEM_NAME_OFFSET;
-                                },
-                                constants::PLOT_TRAYITEM_TYPE | constants::ROOM_TRAYITEM_TYPE => {
-                                    name_size = content[constants::ROOM_PLOT_TRAYITEM_NAME_OFFSET];
-                                    offset = constants::ROOM_PLOT_TRAYITEM_NAME_OFFSET;
-                                },
+                                constants::HOUSEHOLD_TRAYITEM_TYPE |
+                                constants::PLOT_TRAYITEM_TYPE |
+                                constants::ROOM_TRAYITEM_TYPE => {},
                                 _ => {
-                                    println!("{} Unknown tray item type, skipping file ({}).", "[Warning!]".yellow(), path.display());
+                                    utils::print_warning(format!("Unknown tray item type, skipping file ({}).", path.display()));
                                     return None;
                                 }
                             }
-                            let name = utils::extract_string(&content, offset + 1, name_size);
+                            let metadata = match TrayMetadata::parse(&content) {
+                                Some(metadata) => metadata,
+                                None => {
+                                    utils::print_warning(format!("Couldn't decode tray item metadata, skipping file ({}).", path.display()));
+                                    return None;
+                                }
+                            };
+                            let modified = match fs::metadata(path).and_then(|file_metadata| file_metadata.modified()) {
+                                Ok(modified) => modified,
+                                Err(_) => SystemTime::UNIX_EPOCH
+                            };
+                            utils::print_trace(format!("{} is {} by {}", file_name, metadata.name, metadata.creator_name));
                             return Some(TrayItem {
-                                path: path.clone(),
-                                name,
+                                path: path.to_path_buf(),
+                                name: metadata.name,
+                                creator_name: metadata.creator_name,
+                                creator_id: metadata.creator_id,
                                 id,
                                 file_type,
-                                filename: String::from(file_name)
+                                filename: String::from(file_name),
+                                modified,
                             });
                         } else {
-                            println!("{} Couldn't parse file name, skipping! ({})", "[Warning!]".yellow(), path.display());
+                            utils::print_warning(format!("Couldn't parse file name, skipping! ({})", path.display()));
                         }
                     } else {
-                        println!("{} Couldn't get file name, skipping! ({})", "[Warning!]".yellow(), path.display());
+                        utils::print_warning(format!("Couldn't get file name, skipping! ({})", path.display()));
                     }
                 },
                 Err(_) => {
-                    println!("{} Couldn't read tray item file, the whole gallery item will be skipped!", "[Warning!]".yellow());
+                    utils::print_warning(String::from("Couldn't read tray item file, the whole gallery item will be skipped!"));
                 }
             }
         }
@@ -68,29 +95,30 @@ impl TrayItem {
 pub struct TrayItemFile {
     pub path: PathBuf,
     pub id: u64,
+    #[allow(dead_code)]
     pub file_type: u32,
     pub filename: String,
 }
 
 impl TrayItemFile {
-    pub fn new(path: &PathBuf) -> Option<TrayItemFile> {
+    pub fn new(path: &Path) -> Option<TrayItemFile> {
         if let Some((file_type, id)) = utils::extract_id_and_type(path) {
             if let Some(filename) = path.file_name() {
                 if let Some(filename) = filename.to_str() {
                     return Some(TrayItemFile {
-                        path: path.clone(),
+                        path: path.to_path_buf(),
                         id,
                         file_type,
                         filename: String::from(filename),
                     });
                 } else {
-                    println!("{} Couldn't parse file name, skipping! ({})", "[Warning!]".yellow(), path.display());
+                    utils::print_warning(format!("Couldn't parse file name, skipping! ({})", path.display()));
                 }
             } else {
-                println!("{} Couldn't get file name, skipping! ({})", "[Warning!]".yellow(), path.display());
+                utils::print_warning(format!("Couldn't get file name, skipping! ({})", path.display()));
             }
         } else {
-            println!("{} Couldn't extract id and type from file name, skipping! ({})", "[Warning!]".yellow(), path.display());
+            utils::print_warning(format!("Couldn't extract id and type from file name, skipping! ({})", path.display()));
         }
         return None;
     }
@@ -0,0 +1,104 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use directories::{BaseDirs, UserDirs};
+
+use crate::{constants, utils};
+
+/// Searches the native documents folder and every known Wine, Proton, Lutris and Bottles prefix for Tray folders.
+pub fn find_tray_folders() -> Vec<PathBuf> {
+    let mut tray_folders: Vec<PathBuf> = Vec::new();
+    let mut canonical_folders: Vec<PathBuf> = Vec::new();
+    for documents_folder in find_documents_folders() {
+        for sims_folder_name in constants::SIMS_FOLDER_NAMES {
+            let tray_folder = documents_folder.join(constants::EA_FOLDER_NAME).join(sims_folder_name).join(constants::TRAY_FOLDER_NAME);
+            utils::print_trace(format!("Looking for a Tray folder at {}", tray_folder.display()));
+            if tray_folder.is_dir() {
+                // Wine usually links the prefix documents folder to the native one, so the same folder can show up twice.
+                let canonical_folder = fs::canonicalize(&tray_folder).unwrap_or(tray_folder.clone());
+                if !canonical_folders.contains(&canonical_folder) {
+                    canonical_folders.push(canonical_folder);
+                    tray_folders.push(tray_folder);
+                }
+            }
+        }
+    }
+    return tray_folders;
+}
+
+fn find_documents_folders() -> Vec<PathBuf> {
+    let mut documents_folders: Vec<PathBuf> = Vec::new();
+    if let Some(user_dirs) = UserDirs::new() {
+        if let Some(document_dir) = user_dirs.document_dir() {
+            documents_folders.push(document_dir.to_path_buf());
+        }
+    }
+    for prefix in find_wine_prefixes() {
+        for user_folder in list_folders(&prefix.join("drive_c").join("users")) {
+            for documents_folder_name in constants::WINE_DOCUMENTS_FOLDER_NAMES {
+                documents_folders.push(user_folder.join(documents_folder_name));
+            }
+        }
+    }
+    return documents_folders;
+}
+
+fn find_wine_prefixes() -> Vec<PathBuf> {
+    let mut prefixes: Vec<PathBuf> = Vec::new();
+    if let Some(prefix) = env::var_os("WINEPREFIX") {
+        prefixes.push(PathBuf::from(prefix));
+    }
+    if let Some(base_dirs) = BaseDirs::new() {
+        let home_dir = base_dirs.home_dir();
+        let data_dir = base_dirs.data_dir();
+        prefixes.push(home_dir.join(".wine"));
+        prefixes.extend(list_folders(&home_dir.join("Games")));
+        prefixes.extend(list_folders(&data_dir.join("bottles").join("bottles")));
+        prefixes.extend(list_folders(&home_dir.join(".var").join("app").join("com.usebottles.bottles").join("data").join("bottles").join("bottles")));
+        let steam_roots = [
+            home_dir.join(".steam").join("steam"),
+            data_dir.join("Steam"),
+            home_dir.join(".var").join("app").join("com.valvesoftware.Steam").join(".local").join("share").join("Steam"),
+        ];
+        let mut steam_libraries: Vec<PathBuf> = Vec::new();
+        for steam_root in steam_roots {
+            steam_libraries.extend(read_steam_libraries(&steam_root));
+            steam_libraries.push(steam_root);
+        }
+        for steam_library in steam_libraries {
+            for compat_folder in list_folders(&steam_library.join("steamapps").join("compatdata")) {
+                prefixes.push(compat_folder.join("pfx"));
+            }
+        }
+    }
+    return prefixes;
+}
+
+/// Reads the extra library folders from Steam's `libraryfolders.vdf`.
+fn read_steam_libraries(steam_root: &Path) -> Vec<PathBuf> {
+    let mut libraries: Vec<PathBuf> = Vec::new();
+    if let Ok(content) = fs::read_to_string(steam_root.join("steamapps").join("libraryfolders.vdf")) {
+        for line in content.lines() {
+            let parts: Vec<&str> = line.split('"').filter(|part| !part.trim().is_empty()).collect();
+            if parts.len() == 2 && parts[0] == "path" {
+                libraries.push(PathBuf::from(parts[1].replace("\\\\", "\\")));
+            }
+        }
+    }
+    return libraries;
+}
+
+fn list_folders(path: &Path) -> Vec<PathBuf> {
+    let mut folders: Vec<PathBuf> = Vec::new();
+    if let Ok(items) = fs::read_dir(path) {
+        for item in items.flatten() {
+            let item_path = item.path();
+            if item_path.is_dir() {
+                folders.push(item_path);
+            }
+        }
+    }
+    folders.sort();
+    return folders;
+}
@@ -0,0 +1,167 @@
+use std::mem;
+
+use crate::constants;
+use crate::protobuf::{self, WireValue};
+
+pub struct TrayMetadata {
+    pub name: String,
+    pub description: String,
+    pub creator_name: String,
+    pub creator_id: u64,
+}
+
+impl TrayMetadata {
+    pub fn parse(content: &[u8]) -> Option<TrayMetadata> {
+        let message = content.get(constants::TRAYITEM_HEADER_SIZE..)?;
+        let fields = protobuf::read_fields(message)?;
+        let mut metadata = TrayMetadata {
+            name: String::new(),
+            description: String::new(),
+            creator_name: String::new(),
+            creator_id: 0,
+        };
+        for field in fields {
+            match (field.number, field.value) {
+                (constants::TRAYITEM_NAME_FIELD, WireValue::LengthDelimited(value)) => {
+                    metadata.name = String::from_utf8_lossy(value).to_string();
+                },
+                (constants::TRAYITEM_DESCRIPTION_FIELD, WireValue::LengthDelimited(value)) => {
+                    metadata.description = String::from_utf8_lossy(value).to_string();
+                },
+                (constants::TRAYITEM_CREATOR_ID_FIELD, WireValue::Varint(value)) => {
+                    metadata.creator_id = value;
+                },
+                (constants::TRAYITEM_CREATOR_NAME_FIELD, WireValue::LengthDelimited(value)) => {
+                    metadata.creator_name = String::from_utf8_lossy(value).to_string();
+                },
+                _ => {}
+            }
+        }
+        return Some(metadata);
+    }
+}
+
+pub enum FieldValue {
+    Text(String),
+    Number(u64),
+}
+
+/// Rewrites the trayitem with new values for some top level fields, a field missing from the file is appended.
+/// Every other field keeps its bytes, and the header is updated with the new message length. A field stored with
+/// another wire type than the new value gives None, the game couldn't read the item anymore.
+pub fn set_fields(content: &[u8], values: &[(u32, FieldValue)]) -> Option<Vec<u8>> {
+    let message = content.get(constants::TRAYITEM_HEADER_SIZE..)?;
+    let mut fields = protobuf::read_fields(message)?;
+    for (number, value) in values {
+        let wire_value = match value {
+            FieldValue::Text(text) => WireValue::LengthDelimited(text.as_bytes()),
+            FieldValue::Number(number) => WireValue::Varint(*number)
+        };
+        match fields.iter_mut().find(|field| field.number == *number) {
+            Some(field) => {
+                if mem::discriminant(&field.value) != mem::discriminant(&wire_value) {
+                    return None;
+                }
+                field.value = wire_value;
+            },
+            None => {
+                fields.push(protobuf::Field { number: *number, value: wire_value });
+            }
+        }
+    }
+    return Some(with_header(&content[..constants::TRAYITEM_HEADER_SIZE], &protobuf::write_fields(&fields)));
+}
+
+/// The header is 4 bytes the game leaves empty followed by the message length.
+pub fn with_header(header: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut content = header[..4].to_vec();
+    content.extend_from_slice(&(message.len() as u32).to_le_bytes());
+    content.extend_from_slice(message);
+    return content;
+}
+
+/// The message behind the header, when the header is 4 empty bytes followed by the length of the rest of the file.
+pub fn get_message(content: &[u8]) -> Option<&[u8]> {
+    let header = content.get(..constants::TRAYITEM_HEADER_SIZE)?;
+    if header[..4] != [0u8; 4] || header[4..] != ((content.len() - constants::TRAYITEM_HEADER_SIZE) as u32).to_le_bytes() {
+        return None;
+    }
+    return Some(&content[constants::TRAYITEM_HEADER_SIZE..]);
+}
+
+pub fn get_field_name(number: u32) -> Option<&'static str> {
+    match number {
+        constants::TRAYITEM_ID_FIELD => {
+            return Some("id");
+        },
+        constants::TRAYITEM_TYPE_FIELD => {
+            return Some("type");
+        },
+        constants::TRAYITEM_REMOTE_ID_FIELD => {
+            return Some("remote id");
+        },
+        constants::TRAYITEM_NAME_FIELD => {
+            return Some("name");
+        },
+        constants::TRAYITEM_DESCRIPTION_FIELD => {
+            return Some("description");
+        },
+        constants::TRAYITEM_CREATOR_ID_FIELD => {
+            return Some("creator id");
+        },
+        constants::TRAYITEM_CREATOR_NAME_FIELD => {
+            return Some("creator name");
+        },
+        _ => {
+            return None;
+        }
+    }
+}
+
+/// Every top level field of the trayitem message, formatted for display, in file order.
+pub fn read_field_values(content: &[u8]) -> Option<Vec<(u32, String)>> {
+    let message = content.get(constants::TRAYITEM_HEADER_SIZE..)?;
+    let fields = protobuf::read_fields(message)?;
+    return Some(fields.iter().map(|field| (field.number, protobuf::format_value(&field.value))).collect());
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A household trayitem laid out like the one in the README: id, type, remote id, name, description,
+    /// then `30 <varint>` for the creator id and `3A <length>` for the creator name.
+    pub(crate) fn build_trayitem(name: &str, creator_id: u64, creator_name: &str) -> Vec<u8> {
+        let mut message = vec![0x08, 0xC7, 0x80, 0xA8, 0xF4, 0x90, 0xDE, 0x84, 0xDF, 0x07, 0x10, 0x02, 0x1A, 0x10];
+        message.extend_from_slice(&[0u8; 16]);
+        for (tag, text) in [(0x22, name), (0x2A, "A family of four")] {
+            message.push(tag);
+            message.push(text.len() as u8);
+            message.extend_from_slice(text.as_bytes());
+        }
+        message.push(0x30);
+        protobuf::write_varint(&mut message, creator_id);
+        message.push(0x3A);
+        message.push(creator_name.len() as u8);
+        message.extend_from_slice(creator_name.as_bytes());
+        return with_header(&[0u8; 4], &message);
+    }
+
+    #[test]
+    fn parse_reads_the_readme_layout() {
+        let content = build_trayitem("Villa Rosa", 0x1234_5678_9abc, "simmer42");
+        assert_eq!(&content[4..8], &((content.len() - constants::TRAYITEM_HEADER_SIZE) as u32).to_le_bytes());
+        let metadata = TrayMetadata::parse(&content).unwrap();
+        assert_eq!(metadata.name, "Villa Rosa");
+        assert_eq!(metadata.description, "A family of four");
+        assert_eq!(metadata.creator_id, 0x1234_5678_9abc);
+        assert_eq!(metadata.creator_name, "simmer42");
+    }
+
+    #[test]
+    fn set_fields_refuses_another_wire_type() {
+        let content = build_trayitem("Villa Rosa", 0x1234_5678_9abc, "simmer42");
+        assert!(set_fields(&content, &[(constants::TRAYITEM_CREATOR_ID_FIELD, FieldValue::Text(String::from("Anonymous")))]).is_none());
+        assert!(set_fields(&content, &[(constants::TRAYITEM_NAME_FIELD, FieldValue::Number(7))]).is_none());
+    }
+}
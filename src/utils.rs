@@ -1,164 +1,251 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::fs;
-use std::fs::ReadDir;
-use std::io::stdin;
-use std::io::ErrorKind;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::SystemTime;
 use crate::constants;
+use crate::logging::{self, Level};
+use crate::prompts;
+use crate::tray_location;
 use crate::tray_files::TrayFolderContent;
 use crate::tray_files::TrayItem;
 use crate::tray_files::TrayItemFile;
-use colored::Colorize;
-use directories::UserDirs;
-
-pub fn get_tray_folder() -> Option<ReadDir> {
-    match UserDirs::new() {
-        Some(user_dirs) => {
-            match user_dirs.document_dir() {
-                Some(document_dir) => {
-                    let tray_folder = document_dir.join("Electronic Arts").join("The Sims 4").join("Tray");
-                    match fs::read_dir(tray_folder) {
-                        Ok(items) => {
-                            return Some(items);
-                        },
-                        Err(e) => {
-                            if e.kind() == ErrorKind::NotFound {
-                                print_error(String::from("Tray folder doesn't exists!"));
-                            } else {
-                                print_error(String::from("Unable to read the tray folder!"));
-                            }
-                            return None;
-                        }
-                    }
-                },
-                None => {
-                    print_error(String::from("Couldn't find the document directory"));
+use chrono::{DateTime, Local};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+/// How a command ended, the process exits with the matching code.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    NothingToDo,
+    PartialFailure,
+    Failure,
+}
+
+impl Outcome {
+    /// Failure when nothing could be done, partial failure when only some of the work failed.
+    pub fn from_counts(succeeded: usize, failed: usize) -> Outcome {
+        if failed == 0 {
+            return Outcome::Success;
+        }
+        if succeeded == 0 {
+            return Outcome::Failure;
+        }
+        return Outcome::PartialFailure;
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Success => constants::EXIT_SUCCESS,
+            Outcome::NothingToDo => constants::EXIT_NOTHING_TO_DO,
+            Outcome::PartialFailure => constants::EXIT_PARTIAL_FAILURE,
+            Outcome::Failure => constants::EXIT_FAILURE
+        }
+    }
+}
+
+static WARNING_COUNT: AtomicUsize = AtomicUsize::new(0);
+static ERROR_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub fn get_tray_folder(tray_override: Option<&Path>) -> Option<PathBuf> {
+    let tray_folder = find_tray_folder(tray_override);
+    if let Some(tray_folder) = &tray_folder {
+        print_debug(format!("Using Tray folder {}", tray_folder.display()));
+    }
+    return tray_folder;
+}
+
+fn find_tray_folder(tray_override: Option<&Path>) -> Option<PathBuf> {
+    match tray_override {
+        Some(tray_folder) => {
+            if tray_folder.is_dir() {
+                return Some(tray_folder.to_path_buf());
+            }
+            print_error(format!("Tray folder {} doesn't exists!", tray_folder.display()));
+            return None;
+        },
+        None => {
+            let mut tray_folders = tray_location::find_tray_folders();
+            match tray_folders.len() {
+                0 => {
+                    print_error(String::from("Couldn't find the Tray folder, use --tray to set its location!"));
                     return None;
+                },
+                1 => {
+                    return Some(tray_folders.remove(0));
+                },
+                _ => {
+                    return prompts::pick_tray_folder(tray_folders);
                 }
             }
+        }
+    }
+}
+
+/// The output folder is `output` in the current directory unless another one is given.
+pub fn get_output_folder(output_override: Option<&Path>) -> Option<PathBuf> {
+    match output_override {
+        Some(output_path) => {
+            return Some(output_path.to_path_buf());
         },
         None => {
-            print_error(String::from("Couldn't find user directories"));
-            return None;
+            match env::current_dir() {
+                Ok(current_dir) => {
+                    return Some(current_dir.join("output"));
+                },
+                Err(_) => {
+                    print_error(String::from("Cound't find the current directory"));
+                    return None;
+                }
+            }
         }
     }
 }
 
-pub fn prepare_output_folder() -> Option<PathBuf> {
-    match env::current_dir() {
-        Ok(current_dir) => {
-            let output_path = current_dir.join("output");
-            match fs::create_dir(&output_path) {
+/// Creates the output folder with a marker file. An existing folder is only emptied when the marker shows this tool
+/// created it, any other non-empty folder is only deleted after asking.
+pub fn prepare_output_folder(output_override: Option<&Path>) -> Option<PathBuf> {
+    match get_output_folder(output_override) {
+        Some(output_path) => {
+            if output_path.exists() && !clear_output_folder(&output_path) {
+                return None;
+            }
+            match fs::create_dir_all(&output_path).and_then(|_| fs::write(output_path.join(constants::OUTPUT_MARKER_FILENAME), "")) {
                 Ok(_) => {
                     return Some(output_path);
                 },
-                Err(e) => {
-                    if e.kind() == ErrorKind::AlreadyExists {
-                        match fs::remove_dir_all(&output_path) {
-                            Ok(_) => {
-                                match fs::create_dir(&output_path) {
-                                    Ok(_) => {
-                                        return Some(output_path);
-                                    },
-                                    Err(_) => {
-                                        print_error(String::from("Cound't create the output directory"));
-                                        return None;
-                                    }
-                                }
-                            },
-                            Err(_) => {
-                                print_warning(String::from("Output folder already exists and couldn't be removed"));
-                                print_info(String::from("Process can continue but it may fail. Do you want to continue? [y/N]"));
-                                let mut input = String::new();
-                                loop {
-                                    match stdin().read_line(&mut input) {
-                                        Ok(_) => {
-                                            let normalized_input = input.trim().to_lowercase();
-                                            let normalized_input = normalized_input.as_str();
-                                            match normalized_input {
-                                                "y" | "yes" => {
-                                                    return Some(output_path);
-                                                },
-                                                "n" | "no" | "" => {
-                                                    return None;
-                                                },
-                                                _ => {
-                                                    print_info(String::from("Invalid input. Do you want to continue? [y/N]"));
-                                                }
-                                            }
-                                        },
-                                        Err(_) => {
-                                            print_error(String::from("Couldn't read input."));
-                                            return None;
-                                        }
-                                    }
-                                }
-                            }
+                Err(_) => {
+                    print_error(String::from("Cound't create the output directory"));
+                    return None;
+                }
+            }
+        },
+        None => {
+            return None;
+        }
+    }
+}
+
+fn clear_output_folder(output_path: &Path) -> bool {
+    let is_empty = match fs::read_dir(output_path) {
+        Ok(mut content) => content.next().is_none(),
+        Err(_) => {
+            print_error(format!("{} already exists and isn't a readable folder!", output_path.display()));
+            return false;
+        }
+    };
+    if is_empty {
+        return true;
+    }
+    if !output_path.join(constants::OUTPUT_MARKER_FILENAME).is_file() {
+        let message = format!("{} already exists and wasn't created by this tool, everything in it will be deleted. Do you want to continue?", output_path.display());
+        if !prompts::confirm(message) {
+            print_error(String::from("The output folder isn't empty, choose another one with --output!"));
+            return false;
+        }
+    }
+    match fs::remove_dir_all(output_path) {
+        Ok(_) => {
+            return true;
+        },
+        Err(_) => {
+            print_warning(String::from("Output folder already exists and couldn't be removed"));
+            return prompts::confirm(String::from("Process can continue but it may fail. Do you want to continue?"));
+        }
+    }
+}
+
+pub fn list_tray_folder(tray_folder: &Path) -> Option<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    match fs::read_dir(tray_folder) {
+        Ok(content) => {
+            for item in content {
+                match item {
+                    Ok(item) => {
+                        let path = item.path();
+                        if path.is_file() {
+                            files.push(path);
                         }
-                    } else {
-                        print_error(String::from("Cound't create the output directory"));
-                        return None;
+                    },
+                    Err(_) => {
+                        print_warning(String::from("Couldn't read item in tray folder, skipping!"));
                     }
                 }
             }
         },
         Err(_) => {
-            print_error(String::from("Cound't find the current directory"));
+            print_error(String::from("Unable to read the tray folder!"));
             return None;
         }
     }
+    files.sort();
+    return Some(files);
+}
+
+pub fn list_files_recursive(folder: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    if let Ok(content) = fs::read_dir(folder) {
+        for item in content.flatten() {
+            let path = item.path();
+            if path.is_dir() {
+                files.extend(list_files_recursive(&path));
+            } else if path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    return files;
 }
 
-pub fn read_tray_files(content: ReadDir) -> TrayFolderContent {
-    let mut trayitem_files: Vec<TrayItem> = Vec::new();
+pub fn read_tray_files(tray_folder: &Path) -> TrayFolderContent {
+    let mut trayitem_paths: Vec<PathBuf> = Vec::new();
     let mut unknown_files: Vec<TrayItemFile> = Vec::new();
     let mut sgi_files: Vec<TrayItemFile> = Vec::new();
-    for item in content {
-        match item {
-            Ok(item) => {
-                let path = item.path();
-                if path.is_file() {
-                    if let Some(extension) = path.extension() {
-                        if let Some(extension) = extension.to_str() {
-                            match extension {
-                                constants::TRAYITEM_EXT => {
-                                    if let Some(trayitem) = TrayItem::new(&path) {
-                                        trayitem_files.push(trayitem);
-                                    }
-                                },
-                                constants::HOUSEHOLDBINARY_EXT |
-                                constants::HHI_EXT |
-                                constants::BLUEPRINT_EXT |
-                                constants::BPI_EXT |
-                                constants::ROOM_EXT |
-                                constants::RMI_EXT  => {
-                                    if let Some(unknown_file) = TrayItemFile::new(&path) {
-                                        unknown_files.push(unknown_file);
-                                    }
-                                }
-                                constants::SGI_EXT => {
-                                    if let Some(sgi_file) = TrayItemFile::new(&path) {
-                                        sgi_files.push(sgi_file);
-                                    }
-                                },
-                                _ => {
-                                    print_warning(String::from("Unknown extension, skipping file."));
-                                }
-                            }
-                        } else {
-                            print_warning(String::from("Couldn't parse file extension, skipping!"));
+    for path in list_tray_folder(tray_folder).unwrap_or_default() {
+        if let Some(extension) = path.extension() {
+            if let Some(extension) = extension.to_str() {
+                match extension {
+                    constants::TRAYITEM_EXT => {
+                        trayitem_paths.push(path);
+                    },
+                    constants::HOUSEHOLDBINARY_EXT |
+                    constants::HHI_EXT |
+                    constants::BLUEPRINT_EXT |
+                    constants::BPI_EXT |
+                    constants::ROOM_EXT |
+                    constants::RMI_EXT  => {
+                        if let Some(unknown_file) = TrayItemFile::new(&path) {
+                            unknown_files.push(unknown_file);
                         }
-                    } else {
-                        print_warning(format!("Couldn't get file extension, skipping! ({})", path.display()));
+                    }
+                    constants::SGI_EXT => {
+                        if let Some(sgi_file) = TrayItemFile::new(&path) {
+                            sgi_files.push(sgi_file);
+                        }
+                    },
+                    _ => {
+                        print_warning(format!("Unknown extension, skipping file. ({})", path.display()));
                     }
                 }
-            },
-            Err(_) => {
-                print_warning(String::from("Couldn't read item in tray folder, skipping!"));
+            } else {
+                print_warning(String::from("Couldn't parse file extension, skipping!"));
             }
-            
+        } else {
+            print_warning(format!("Couldn't get file extension, skipping! ({})", path.display()));
         }
     }
+    // Reading and decoding the trayitems is the slow part on large folders.
+    let trayitem_files: Vec<TrayItem> = parallel_map(&trayitem_paths, |path| TrayItem::new(path)).into_iter().flatten().collect();
+    print_debug(format!("Found {} gallery items, {} binary files and {} thumbnails in {}.", trayitem_files.len(), unknown_files.len(), sgi_files.len(), tray_folder.display()));
     return TrayFolderContent {
         trayitem_files,
         unknown_files,
@@ -166,12 +253,47 @@ pub fn read_tray_files(content: ReadDir) -> TrayFolderContent {
     }
 }
 
-pub fn extract_id_and_type(path: &PathBuf) -> Option<(u32, u64)> {
+/// Runs `task` on every item across all cores and returns the results in the order of the items.
+/// What a task prints is shown once the tasks before it are done, so the output reads the same as a serial run.
+pub fn parallel_map<T: Sync, R: Send>(items: &[T], task: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let (sender, receiver) = mpsc::channel();
+    let mut results: Vec<R> = Vec::with_capacity(items.len());
+    thread::scope(|scope| {
+        let task = &task;
+        scope.spawn(move || {
+            items.par_iter().enumerate().for_each_with(sender, |sender, (index, item)| {
+                logging::start_capture();
+                let result = task(item);
+                let _ = sender.send((index, logging::finish_capture(), result));
+            });
+        });
+        let mut finished: BTreeMap<usize, (Vec<logging::Record>, R)> = BTreeMap::new();
+        for (index, records, result) in receiver {
+            finished.insert(index, (records, result));
+            while let Some((records, result)) = finished.remove(&results.len()) {
+                for record in &records {
+                    logging::write_record(record);
+                }
+                results.push(result);
+            }
+        }
+    });
+    return results;
+}
+
+pub fn extract_id_and_type(path: &Path) -> Option<(u32, u64)> {
     if let Some(stem) = path.file_stem() {
         if let Some(stem) = stem.to_str() {
             let parts: Vec<&str> = stem.split("!").collect();
             if parts.len() == 2 {
-                let trimmed = parts[1][4..].to_string();
+                // The first byte of the instance is a counter, see the numbered thumbnails.
+                let trimmed = match parts[1].get(4..) {
+                    Some(trimmed) => trimmed,
+                    None => {
+                        print_warning(format!("Couldn't parse id from file name, skipping! ({})", path.display()));
+                        return None;
+                    }
+                };
                 let flat_id = format!("00{}", trimmed);
                 match u64::from_str_radix(&flat_id, 16) {
                     Ok(id) => {
@@ -200,64 +322,218 @@ pub fn extract_id_and_type(path: &PathBuf) -> Option<(u32, u64)> {
     return None;
 }
 
-pub fn extract_string(content: &Vec<u8>, offset: usize, length: u8) -> String {
-    let raw = content[offset..(offset + length as usize)].to_vec();
-    let mut result = String::new();
-    for byte in raw {
-        result.push(byte as char);
-    }
-    return result;
-}
-
-pub fn preapare_output_folder_for_type(outputfolder: &PathBuf, filetype: u32) -> Option<PathBuf> {
-    let folder_name: String;
+pub fn get_type_folder_name(filetype: u32) -> Option<&'static str> {
     match filetype {
         constants::HOUSEHOLD_TRAYITEM_TYPE => {
-            folder_name = String::from(constants::HOUSEHOLD_FOLDERNAME);
+            return Some(constants::HOUSEHOLD_FOLDERNAME);
         },
         constants::PLOT_TRAYITEM_TYPE => {
-            folder_name = String::from(constants::PLOT_FOLDERNAME);
+            return Some(constants::PLOT_FOLDERNAME);
         },
         constants::ROOM_TRAYITEM_TYPE => {
-            folder_name = String::from(constants::ROOM_FOLDERNAME);
+            return Some(constants::ROOM_FOLDERNAME);
         },
         _ => {
-            print_warning(String::from("Unknown gallery item type, skipping!"));
             return None;
         }
     }
-    let target_folder = outputfolder.join(&folder_name);
-    match fs::create_dir(&target_folder) {
-        Ok(_) => {
-            return Some(target_folder);
-        },
-        Err(e) => {
-            if e.kind() == ErrorKind::AlreadyExists {
-                return Some(target_folder);
+}
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    return Ok(hex::encode(hasher.finalize()));
+}
+
+/// Writes next to the target then renames over it, so the target is never left half written.
+pub fn write_file_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let temporary_path = with_suffix(path, ".tmp");
+    let written = File::create(&temporary_path).and_then(|mut file| {
+        file.write_all(content)?;
+        return file.sync_all();
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&temporary_path, path)) {
+        let _ = fs::remove_file(&temporary_path);
+        return Err(e);
+    }
+    return Ok(());
+}
+
+/// Writes every file next to its target, and only once all of them were written renames them into place.
+/// When one file fails the targets are left untouched, see `move_files_into_place`.
+pub fn write_files_atomically(files: &[(&Path, &[u8])]) -> Result<(), String> {
+    let mut temporary_paths: Vec<PathBuf> = Vec::new();
+    for (path, content) in files {
+        let temporary_path = with_suffix(path, ".tmp");
+        let written = match path.parent() {
+            Some(parent) => fs::create_dir_all(parent).and_then(|_| fs::write(&temporary_path, content)),
+            None => fs::write(&temporary_path, content)
+        };
+        temporary_paths.push(temporary_path);
+        if written.is_err() {
+            for temporary_path in &temporary_paths {
+                let _ = fs::remove_file(temporary_path);
             }
-            print_error(format!("Unable to create output folder for {} items!", &folder_name));
-            print_warning(format!("All {} items may be skipped!", &folder_name));
-            return None;
+            return Err(format!("Couldn't write {}", path.display()));
         }
     }
+    let moved_files: Vec<(&Path, &Path)> = temporary_paths.iter().zip(files).map(|(temporary_path, (path, _))| (temporary_path.as_path(), *path)).collect();
+    return move_files_into_place(&moved_files);
+}
+
+/// Renames every staged file over its target, staged files have to be on the same drive as their targets.
+/// The replaced targets are kept aside until every file is in place: when one rename fails, the files already moved
+/// are put back and the targets are left untouched. Only a failure of that rollback leaves some targets replaced,
+/// the error then lists them.
+pub fn move_files_into_place(files: &[(&Path, &Path)]) -> Result<(), String> {
+    let mut moved_files: Vec<(&Path, Option<PathBuf>)> = Vec::new();
+    let mut failed_path: Option<&Path> = None;
+    for (staged_path, path) in files {
+        let backup_path = with_suffix(path, ".bak");
+        let has_backup = path.exists();
+        if has_backup && fs::rename(path, &backup_path).is_err() {
+            failed_path = Some(path);
+            break;
+        }
+        if fs::rename(staged_path, path).is_err() {
+            if has_backup {
+                let _ = fs::rename(&backup_path, path);
+            }
+            failed_path = Some(path);
+            break;
+        }
+        moved_files.push((path, if has_backup { Some(backup_path) } else { None }));
+    }
+    let failed_path = match failed_path {
+        Some(failed_path) => failed_path,
+        None => {
+            for (_, backup_path) in moved_files.iter() {
+                if let Some(backup_path) = backup_path {
+                    let _ = fs::remove_file(backup_path);
+                }
+            }
+            return Ok(());
+        }
+    };
+    for (staged_path, _) in &files[moved_files.len()..] {
+        let _ = fs::remove_file(staged_path);
+    }
+    let mut changed_paths: Vec<String> = Vec::new();
+    for (path, backup_path) in moved_files.iter().rev() {
+        let restored = match backup_path {
+            Some(backup_path) => fs::rename(backup_path, path),
+            None => fs::remove_file(path)
+        };
+        if restored.is_err() {
+            changed_paths.push(path.display().to_string());
+        }
+    }
+    if !changed_paths.is_empty() {
+        return Err(format!("Couldn't move {} into place, and {} couldn't be put back", failed_path.display(), changed_paths.join(", ")));
+    }
+    return Err(format!("Couldn't move {} into place", failed_path.display()));
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(suffix);
+    return PathBuf::from(path);
+}
+
+/// A name that can only point at a file inside the folder it is joined to.
+pub fn is_plain_filename(name: &str) -> bool {
+    return !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\']);
+}
+
+/// Name made of the current date and time, with a counter added when `folder` already holds that name plus `suffix`.
+/// The counter is zero-padded so sorting the names keeps them in creation order.
+pub fn get_new_timestamp_name(folder: &Path, suffix: &str) -> String {
+    let now: DateTime<Local> = DateTime::from(SystemTime::now());
+    let base_name = now.format("%Y-%m-%d_%H-%M-%S").to_string();
+    let mut name = base_name.clone();
+    let mut counter = 1;
+    while folder.join(format!("{}{}", name, suffix)).exists() {
+        name = format!("{}_{:03}", base_name, counter);
+        counter += 1;
+    }
+    return name;
+}
+
+pub fn hash_bytes(content: &[u8]) -> String {
+    return hex::encode(Sha256::digest(content));
+}
+
+pub fn get_warning_count() -> usize {
+    return WARNING_COUNT.load(Ordering::Relaxed);
+}
+
+pub fn get_error_count() -> usize {
+    return ERROR_COUNT.load(Ordering::Relaxed);
 }
 
 pub fn print_success(message: String) {
-    println!("{} {}", "[SUCCESS]".bright_green(), message);
+    logging::log(Level::Info, "SUCCESS", message);
+}
+
+/// Per-file details, only shown with --verbose.
+pub fn print_detail(message: String) {
+    logging::log(Level::Debug, "SUCCESS", message);
 }
 
 pub fn print_warning(message: String) {
-    println!("{} {}", "[WARNING]".yellow(), message);
+    WARNING_COUNT.fetch_add(1, Ordering::Relaxed);
+    logging::log(Level::Warning, "WARNING", message);
 }
 
 pub fn print_error(message: String) {
-    println!("{} {}", "[ERROR]".red(), message);
+    ERROR_COUNT.fetch_add(1, Ordering::Relaxed);
+    logging::log(Level::Error, "ERROR", message);
 }
 
 pub fn print_info(message: String) {
-    println!("{} {}", "[INFO]".bright_blue(), message);
+    logging::log(Level::Info, "INFO", message);
 }
 
 pub fn print_debug(message: String) {
-    println!("{} {}", "[DEBUG]".bright_magenta(), message);
-}
\ No newline at end of file
+    logging::log(Level::Debug, "DEBUG", message);
+}
+
+pub fn print_trace(message: String) {
+    logging::log(Level::Trace, "TRACE", message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_id_and_type_skips_short_or_non_ascii_instances() {
+        assert_eq!(extract_id_and_type(Path::new("0x00000001!0x00aa12f12d6202c5.trayitem")), Some((1, 0xaa12f12d6202c5)));
+        assert_eq!(extract_id_and_type(Path::new("0x00000000!0x0daa12f12d6202c6.sgi")), Some((0, 0xaa12f12d6202c6)));
+        assert_eq!(extract_id_and_type(Path::new("0x00000000!0x1.sgi")), None);
+        assert_eq!(extract_id_and_type(Path::new("0x00000000!0xaé12.sgi")), None);
+    }
+
+    #[test]
+    fn move_files_into_place_puts_back_moved_files_on_failure() {
+        let folder = env::temp_dir().join(format!("{}-move-test-{}", constants::APPLICATION_NAME, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let (first_path, first_staged_path) = (folder.join("first"), folder.join("first.staged"));
+        let (second_path, second_staged_path) = (folder.join("missing").join("second"), folder.join("second.staged"));
+        let (new_path, new_staged_path) = (folder.join("new"), folder.join("new.staged"));
+        fs::write(&first_path, "old").unwrap();
+        for staged_path in [&first_staged_path, &new_staged_path, &second_staged_path] {
+            fs::write(staged_path, "new").unwrap();
+        }
+        let files: Vec<(&Path, &Path)> = vec![(&first_staged_path, &first_path), (&new_staged_path, &new_path), (&second_staged_path, &second_path)];
+        assert!(move_files_into_place(&files).is_err());
+        assert_eq!(fs::read_to_string(&first_path).unwrap(), "old");
+        assert!(!new_path.exists());
+        let mut names: Vec<String> = fs::read_dir(&folder).unwrap().flatten().map(|item| item.file_name().to_string_lossy().to_string()).collect();
+        names.sort();
+        assert_eq!(names, vec![String::from("first")]);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cli::WatchArgs;
+use crate::filters::TrayItemFilter;
+use crate::naming::NameTemplate;
+use crate::packing::Packer;
+use crate::tray_files::{TrayFolderContent, TrayItem};
+use crate::utils::Outcome;
+use crate::utils;
+
+/// Size and modification time of every file of the Tray folder, a change of either means the game saved it again.
+type FolderState = HashMap<PathBuf, (u64, SystemTime)>;
+
+/// Packs again every item the game saves, once all its files are written, until the user stops it with Ctrl+C.
+/// The output folder is kept, only the pack of the saved item is replaced.
+pub fn watch(tray_override: Option<&Path>, mods_override: Option<&Path>, watch_args: &WatchArgs, name_template: &NameTemplate, filter: &TrayItemFilter) -> Outcome {
+    if watch_args.pack.pick {
+        utils::print_error(String::from("Items can't be picked in watch mode, select them with the filters instead!"));
+        return Outcome::Failure;
+    }
+    let tray_folder = match utils::get_tray_folder(tray_override) {
+        Some(tray_folder) => tray_folder,
+        None => {
+            return Outcome::Failure;
+        }
+    };
+    let packer = match Packer::new(&tray_folder, mods_override, &watch_args.pack, name_template) {
+        Some(packer) => packer,
+        None => {
+            return Outcome::Failure;
+        }
+    };
+    let output_folder = match utils::get_output_folder(watch_args.pack.output.as_deref()) {
+        Some(output_folder) => output_folder,
+        None => {
+            return Outcome::Failure;
+        }
+    };
+    if fs::create_dir_all(&output_folder).is_err() {
+        utils::print_error(String::from("Cound't create the output directory"));
+        return Outcome::Failure;
+    }
+    let mut state = match read_folder_state(&tray_folder) {
+        Some(state) => state,
+        None => {
+            return Outcome::Failure;
+        }
+    };
+    // Where every item already lands, so the old pack can be removed when a save renames the item.
+    let mut output_paths: HashMap<u64, PathBuf> = HashMap::new();
+    for tray_item in read_matching_items(&tray_folder, filter).trayitem_files {
+        output_paths.insert(tray_item.id, packer.get_output_path(&output_folder, &packer.get_relative_path(&tray_item)));
+    }
+    utils::print_info(format!("Watching {} for saved gallery items, packing them to {}. Press Ctrl+C to stop.", tray_folder.display(), output_folder.display()));
+    let interval = Duration::from_secs(watch_args.interval);
+    let settle = Duration::from_secs(watch_args.settle);
+    let mut changed_paths: HashSet<PathBuf> = HashSet::new();
+    let mut last_change = Instant::now();
+    loop {
+        thread::sleep(interval);
+        let new_state = match read_folder_state(&tray_folder) {
+            Some(new_state) => new_state,
+            None => {
+                continue;
+            }
+        };
+        let mut changed = false;
+        for (path, metadata) in &new_state {
+            if state.get(path) != Some(metadata) {
+                utils::print_trace(format!("{} changed", path.display()));
+                changed_paths.insert(path.clone());
+                changed = true;
+            }
+        }
+        // A file removed in the middle of a save is written again right after, waiting for it is enough.
+        if changed || new_state.len() != state.len() {
+            last_change = Instant::now();
+        }
+        state = new_state;
+        if changed_paths.is_empty() || last_change.elapsed() < settle {
+            continue;
+        }
+        let tray_content = read_matching_items(&tray_folder, filter);
+        for tray_item in &tray_content.trayitem_files {
+            let companion_files = tray_content.companion_files(tray_item);
+            let saved = changed_paths.contains(&tray_item.path) || companion_files.iter().any(|companion_file| changed_paths.contains(&companion_file.path));
+            if saved {
+                update_item(&packer, &output_folder, &tray_content, tray_item, &mut output_paths);
+            }
+        }
+        changed_paths.clear();
+    }
+}
+
+fn update_item(packer: &Packer, output_folder: &Path, tray_content: &TrayFolderContent, tray_item: &TrayItem, output_paths: &mut HashMap<u64, PathBuf>) {
+    let output_path = packer.get_output_path(output_folder, &packer.get_relative_path(tray_item));
+    if !packer.pack_item_update(output_folder, tray_content, tray_item, &output_path) {
+        utils::print_warning(format!("[{}] Couldn't be packed, the previous pack is kept!", tray_item.name));
+        return;
+    }
+    if let Some(previous_path) = output_paths.insert(tray_item.id, output_path.clone()) {
+        if previous_path != output_path {
+            let removed = if previous_path.is_dir() { fs::remove_dir_all(&previous_path) } else { fs::remove_file(&previous_path) };
+            if removed.is_ok() {
+                utils::print_debug(format!("[{}] Removed the previous pack {}", tray_item.name, previous_path.display()));
+                if let Some(parent) = previous_path.parent() {
+                    let _ = fs::remove_dir(parent);
+                }
+            }
+        }
+    }
+    utils::print_success(format!("[{}] Packed to {}", tray_item.name, output_path.display()));
+}
+
+fn read_folder_state(tray_folder: &Path) -> Option<FolderState> {
+    let mut state: FolderState = HashMap::new();
+    for path in utils::list_tray_folder(tray_folder)? {
+        if let Ok(metadata) = fs::metadata(&path) {
+            state.insert(path, (metadata.len(), metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)));
+        }
+    }
+    return Some(state);
+}
+
+fn read_matching_items(tray_folder: &Path, filter: &TrayItemFilter) -> TrayFolderContent {
+    let mut tray_content = utils::read_tray_files(tray_folder);
+    tray_content.trayitem_files.retain(|tray_item| filter.matches(tray_item));
+    return tray_content;
+}